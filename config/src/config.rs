@@ -1,3 +1,4 @@
+use std::fmt;
 use std::io::Read;
 use std::time::Duration;
 use serde_derive::Serialize; 
@@ -5,30 +6,99 @@ use serde_derive::Deserialize;
 use std::fs::File;
 use std::path::{Path};
 use crate::loader::*;
+use crate::error::ConfigError;
 
 use libc::fcntl;
 
 const DEFAULT_AUX_BUFFER_SIZE: usize = 4096;
+pub const DEFAULT_IJON_BUFFER_SIZE: usize = 0x1000;
+const DEFAULT_STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+/* the host-side watchdog is opt-in */
+const DEFAULT_HOST_TIMEOUT_FACTOR: u32 = 0;
+const DEFAULT_SHM_BASE_DIR: &str = "/dev/shm";
 
-fn into_absolute_path(path_to_sharedir: &str, path_to_file: String) -> String {
+/* Intel-PT supports up to 4 IP filter ranges */
+pub const IPT_FILTER_MAX: usize = 4;
+const IPT_FILTER_ALIGNMENT: u64 = 0x1000;
+
+fn into_absolute_path(path_to_sharedir: &str, path_to_file: String) -> Result<String, ConfigError> {
     let path_to_default_config = Path::new(&path_to_file);
 
     if path_to_default_config.is_relative(){
-        let path = &format!("{}/{}", path_to_sharedir, path_to_file);
-        let absolute_path = Path::new(&path);
-        return absolute_path.canonicalize().unwrap().to_str().unwrap().to_string();
+        let path = format!("{}/{}", path_to_sharedir, path_to_file);
+        match Path::new(&path).canonicalize() {
+            Ok(absolute_path) => Ok(absolute_path.to_string_lossy().to_string()),
+            Err(x) => Err(ConfigError::UnresolvedPath{ path, source: x }),
+        }
     }
     else{
-        return path_to_default_config.to_str().unwrap().to_string();
+        Ok(path_to_file)
     }
 }
 
-#[derive(Clone, Copy, Serialize, Deserialize, Debug)]
+fn required<T>(value: Option<T>, field: &'static str) -> Result<T, ConfigError> {
+    value.ok_or(ConfigError::MissingField(field))
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
 pub struct IptFilter {
     pub a: u64,
     pub b: u64,
 }
 
+/* IP filter as specified in config.ron. Everything but plain ranges refers to the binaries in the
+ * sharedir (by file name) and is resolved by libnyx while loading the config.
+ */
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub enum IptFilterSpec {
+    /* range(0x400000, 0x500000) */
+    #[serde(rename = "range")]
+    Range(u64, u64),
+
+    /* executable segments of a binary: text_of("target_bin") */
+    #[serde(rename = "text_of")]
+    TextOf(String),
+
+    /* a single ELF section of a binary: section("target_bin", ".text") */
+    #[serde(rename = "section")]
+    Section(String, String),
+
+    /* all functions matching a glob pattern (* and ?) within a single binary: symbol_range("parse_*") */
+    #[serde(rename = "symbol_range")]
+    SymbolRange(String),
+}
+
+impl fmt::Display for IptFilterSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IptFilterSpec::Range(a, b) => write!(f, "range({:#x}, {:#x})", a, b),
+            IptFilterSpec::TextOf(module) => write!(f, "text_of({:?})", module),
+            IptFilterSpec::Section(module, section) => write!(f, "section({:?}, {:?})", module, section),
+            IptFilterSpec::SymbolRange(pattern) => write!(f, "symbol_range({:?})", pattern),
+        }
+    }
+}
+
+/* Page-aligns the given ranges (start is rounded down, end is rounded up) and checks them. */
+pub fn ipt_filters_from_ranges(ranges: &[(u64, u64)]) -> Result<[IptFilter; IPT_FILTER_MAX], ConfigError> {
+    if ranges.len() > IPT_FILTER_MAX {
+        return Err(ConfigError::InvalidValue{ field: "ipt_filters", msg: format!("{} ranges specified (at most {} are supported)", ranges.len(), IPT_FILTER_MAX) });
+    }
+
+    let mut filters = [IptFilter{ a: 0, b: 0 }; IPT_FILTER_MAX];
+    for (filter, (a, b)) in filters.iter_mut().zip(ranges.iter()) {
+        if a >= b {
+            return Err(ConfigError::InvalidValue{ field: "ipt_filters", msg: format!("empty range {:#x}-{:#x}", a, b) });
+        }
+        filter.a = a & !(IPT_FILTER_ALIGNMENT - 1);
+        filter.b = match b.checked_add(IPT_FILTER_ALIGNMENT - 1) {
+            Some(x) => x & !(IPT_FILTER_ALIGNMENT - 1),
+            None => return Err(ConfigError::InvalidValue{ field: "ipt_filters", msg: format!("range {:#x}-{:#x} exceeds the address space", a, b) }),
+        };
+    }
+    Ok(filters)
+}
+
 #[derive(Clone, Debug)]
 pub struct QemuKernelConfig {
     pub qemu_binary: String,
@@ -38,21 +108,21 @@ pub struct QemuKernelConfig {
 }
 
 impl QemuKernelConfig{
-    pub fn new_from_loader(default_config_folder: &str, default: QemuKernelConfigLoader, config: QemuKernelConfigLoader) -> Self {
-        let mut qemu_binary = config.qemu_binary.or(default.qemu_binary).expect("no qemu_binary specified");
-        let mut kernel = config.kernel.or(default.kernel).expect("no kernel specified");
-        let mut ramfs = config.ramfs.or(default.ramfs).expect("no ramfs specified");
+    pub fn new_from_loader(default_config_folder: &str, default: QemuKernelConfigLoader, config: QemuKernelConfigLoader) -> Result<Self, ConfigError> {
+        let mut qemu_binary = required(config.qemu_binary.or(default.qemu_binary), "qemu_binary")?;
+        let mut kernel = required(config.kernel.or(default.kernel), "kernel")?;
+        let mut ramfs = required(config.ramfs.or(default.ramfs), "ramfs")?;
         
-        qemu_binary = into_absolute_path(default_config_folder, qemu_binary);
-        kernel = into_absolute_path(default_config_folder, kernel);
-        ramfs = into_absolute_path(default_config_folder, ramfs);
+        qemu_binary = into_absolute_path(default_config_folder, qemu_binary)?;
+        kernel = into_absolute_path(default_config_folder, kernel)?;
+        ramfs = into_absolute_path(default_config_folder, ramfs)?;
 
-        Self{
+        Ok(Self{
             qemu_binary: qemu_binary,
             kernel: kernel,
             ramfs: ramfs,
-            debug: config.debug.or(default.debug).expect("no debug specified"),
-        }
+            debug: required(config.debug.or(default.debug), "debug")?,
+        })
     }
 }
 
@@ -73,22 +143,51 @@ pub struct QemuSnapshotConfig {
 }
 
 impl QemuSnapshotConfig{
-    pub fn new_from_loader(default_config_folder: &str, default: QemuSnapshotConfigLoader, config: QemuSnapshotConfigLoader) -> Self {
+    pub fn new_from_loader(default_config_folder: &str, default: QemuSnapshotConfigLoader, config: QemuSnapshotConfigLoader) -> Result<Self, ConfigError> {
 
-        let mut qemu_binary = config.qemu_binary.or(default.qemu_binary).expect("no qemu_binary specified");
-        let mut hda = config.hda.or(default.hda).expect("no hda specified");
-        let mut presnapshot = config.presnapshot.or(default.presnapshot).expect("no presnapshot specified");
-        qemu_binary = into_absolute_path(default_config_folder, qemu_binary);
-        hda = into_absolute_path(default_config_folder, hda);
-        presnapshot = into_absolute_path(default_config_folder, presnapshot);
+        let mut qemu_binary = required(config.qemu_binary.or(default.qemu_binary), "qemu_binary")?;
+        let mut hda = required(config.hda.or(default.hda), "hda")?;
+        let mut presnapshot = required(config.presnapshot.or(default.presnapshot), "presnapshot")?;
+        qemu_binary = into_absolute_path(default_config_folder, qemu_binary)?;
+        hda = into_absolute_path(default_config_folder, hda)?;
+        presnapshot = into_absolute_path(default_config_folder, presnapshot)?;
 
-        Self{
+        Ok(Self{
             qemu_binary: qemu_binary,
             hda: hda,
             presnapshot: presnapshot,
-            snapshot_path: config.snapshot_path.or(default.snapshot_path).expect("no snapshot_path specified"),
-            debug: config.debug.or(default.debug).expect("no debug specified"),
+            snapshot_path: required(config.snapshot_path.or(default.snapshot_path), "snapshot_path")?,
+            debug: required(config.debug.or(default.debug), "debug")?,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ForkServerConfig {
+    pub args: Vec<String>,
+    pub hide_output: bool,
+    pub input_size: usize,
+    pub env: Vec<String>,
+}
+
+impl ForkServerConfig{
+    pub fn new_from_loader(default_config_folder: &str, default: ForkServerConfigLoader, config: ForkServerConfigLoader) -> Result<Self, ConfigError> {
+        let mut args = required(config.args.or(default.args), "args")?;
+        if args.is_empty() {
+            return Err(ConfigError::InvalidValue{ field: "args", msg: "no target binary specified".to_string() });
+        }
+
+        /* the target binary is resolved like all other binaries; remaining args (such as @@) are passed as is */
+        if Path::new(&format!("{}/{}", default_config_folder, args[0])).exists() {
+            args[0] = into_absolute_path(default_config_folder, args[0].clone())?;
         }
+
+        Ok(Self{
+            args,
+            hide_output: config.hide_output.or(default.hide_output).unwrap_or(false),
+            input_size: required(config.input_size.or(default.input_size), "input_size")?,
+            env: config.env.or(default.env).unwrap_or_default(),
+        })
     }
 }
 
@@ -96,16 +195,19 @@ impl QemuSnapshotConfig{
 pub enum FuzzRunnerConfig {
     QemuKernel(QemuKernelConfig),
     QemuSnapshot(QemuSnapshotConfig),
+    ForkServer(ForkServerConfig),
 }
 
 impl FuzzRunnerConfig{
-    pub fn new_from_loader(default_config_folder: &str, default: FuzzRunnerConfigLoader, config: FuzzRunnerConfigLoader) -> Self {
+    pub fn new_from_loader(default_config_folder: &str, default: FuzzRunnerConfigLoader, config: FuzzRunnerConfigLoader) -> Result<Self, ConfigError> {
         match (default, config){
             (FuzzRunnerConfigLoader::QemuKernel(d),
-            FuzzRunnerConfigLoader::QemuKernel(c)) => { Self::QemuKernel(QemuKernelConfig::new_from_loader(default_config_folder, d, c))},
+            FuzzRunnerConfigLoader::QemuKernel(c)) => { Ok(Self::QemuKernel(QemuKernelConfig::new_from_loader(default_config_folder, d, c)?))},
             (FuzzRunnerConfigLoader::QemuSnapshot(d),
-            FuzzRunnerConfigLoader::QemuSnapshot(c)) => { Self::QemuSnapshot(QemuSnapshotConfig::new_from_loader(default_config_folder, d, c))},
-            _ => panic!("conflicting FuzzRunner configs"),
+            FuzzRunnerConfigLoader::QemuSnapshot(c)) => { Ok(Self::QemuSnapshot(QemuSnapshotConfig::new_from_loader(default_config_folder, d, c)?))},
+            (FuzzRunnerConfigLoader::ForkServer(d),
+            FuzzRunnerConfigLoader::ForkServer(c)) => { Ok(Self::ForkServer(ForkServerConfig::new_from_loader(default_config_folder, d, c)?))},
+            (d, c) => Err(ConfigError::ConflictingRunners{ default: d.name(), config: c.name() }),
         }
     }
 }
@@ -141,6 +243,9 @@ pub struct FuzzerConfig {
     pub write_protected_input_buffer: bool,
     pub cow_primary_size: Option<u64>,
     pub ipt_filters: [IptFilter;4],
+
+    /* symbolic filters which still have to be resolved (see add_ipt_filters()) */
+    pub ipt_filter_specs: Vec<IptFilterSpec>,
     pub target_hash: Option<[u8; 20]>
 }
 impl FuzzerConfig{
@@ -167,43 +272,95 @@ impl FuzzerConfig{
         Some(bytes)
     }
 
-    pub fn new_from_loader(sharedir: &str, default: FuzzerConfigLoader, config: FuzzerConfigLoader) -> Self {
+    pub fn new_from_loader(sharedir: &str, default: FuzzerConfigLoader, config: FuzzerConfigLoader) -> Result<Self, ConfigError> {
 
-        let seed_path = config.seed_path.or(default.seed_path).unwrap();
+        let seed_path = required(config.seed_path.or(default.seed_path), "seed_path")?;
         let seed_path_value = if seed_path.is_empty() {
             None
         }
         else{
-            Some(into_absolute_path(&sharedir, seed_path))
+            Some(into_absolute_path(&sharedir, seed_path)?)
         };
 
         let target_hash = Self::load_target_hash(&sharedir);
 
-        Self{
+        /* ip0..ip3 are kept for compatibility (unused filters are 0-0) */
+        let mut ranges: Vec<(u64, u64)> = [config.ip0, config.ip1, config.ip2, config.ip3].iter()
+            .filter(|x| x.a != 0 || x.b != 0)
+            .map(|x| (x.a, x.b))
+            .collect();
+
+        let specs = match config.ipt_filters.is_empty() {
+            true => default.ipt_filters,
+            false => config.ipt_filters,
+        };
+        let mut ipt_filter_specs = vec![];
+        for spec in specs.into_iter() {
+            match spec {
+                IptFilterSpec::Range(a, b) => ranges.push((a, b)),
+                _ => ipt_filter_specs.push(spec),
+            }
+        }
+        if ranges.len() + ipt_filter_specs.len() > IPT_FILTER_MAX {
+            return Err(ConfigError::InvalidValue{ field: "ipt_filters", msg: format!("{} filters specified (at most {} are supported)", ranges.len() + ipt_filter_specs.len(), IPT_FILTER_MAX) });
+        }
+        let ipt_filters = ipt_filters_from_ranges(&ranges)?;
+
+        Ok(Self{
             spec_path: format!("{}/spec.msgp",sharedir),
-            workdir_path: config.workdir_path.or(default.workdir_path).expect("no workdir_path specified"),
-            bitmap_size: config.bitmap_size.or(default.bitmap_size).expect("no bitmap_size specified"),
+            workdir_path: required(config.workdir_path.or(default.workdir_path), "workdir_path")?,
+            bitmap_size: required(config.bitmap_size.or(default.bitmap_size), "bitmap_size")?,
             input_buffer_size: config.input_buffer_size,
-            mem_limit: config.mem_limit.or(default.mem_limit).expect("no mem_limit specified"),
-            time_limit: config.time_limit.or(default.time_limit).expect("no time_limit specified"),
+            mem_limit: required(config.mem_limit.or(default.mem_limit), "mem_limit")?,
+            time_limit: required(config.time_limit.or(default.time_limit), "time_limit")?,
             seed_path: seed_path_value,
-            dict: config.dict.or(default.dict).expect("no dict specified"),
-            snapshot_placement: config.snapshot_placement.or(default.snapshot_placement).expect("no snapshot_placement specified"),
+            dict: required(config.dict.or(default.dict), "dict")?,
+            snapshot_placement: required(config.snapshot_placement.or(default.snapshot_placement), "snapshot_placement")?,
             dump_python_code_for_inputs: config.dump_python_code_for_inputs.or(default.dump_python_code_for_inputs),
             exit_after_first_crash: config.exit_after_first_crash.unwrap_or(default.exit_after_first_crash.unwrap_or(false)),
             write_protected_input_buffer: config.write_protected_input_buffer,
             cow_primary_size: if config.cow_primary_size != 0 { Some( config.cow_primary_size as u64) } else { None },
-            ipt_filters: [
-                config.ip0,
-                config.ip1,
-                config.ip2,
-                config.ip3,
-            ],
+            ipt_filters,
+            ipt_filter_specs,
             target_hash: target_hash,
-        }
+        })
+    }
+
+    /* Adds resolved filter ranges to the existing ones (all ranges are checked again). */
+    pub fn add_ipt_filters(&mut self, ranges: &[(u64, u64)]) -> Result<(), ConfigError> {
+        let mut all: Vec<(u64, u64)> = self.ipt_filters.iter()
+            .filter(|x| x.a < x.b)
+            .map(|x| (x.a, x.b))
+            .collect();
+        all.extend_from_slice(ranges);
+        self.ipt_filters = ipt_filters_from_ranges(&all)?;
+        Ok(())
     }
 }
 
+/* Backing of the shm buffers (bitmap, ijon, input) shared with QEMU-Nyx. */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShmBacking {
+    /* regular files in a tmpfs directory (RuntimeConfig::shm_base_dir) */
+    Tmpfs,
+
+    /* files on a hugetlbfs mount (RuntimeConfig::shm_base_dir); sizes are rounded up to the huge page size */
+    Hugetlbfs,
+
+    /* anonymous memfds (QEMU-Nyx opens them via /proc/<pid>/fd/<fd>); nothing is left behind on crashes */
+    Memfd,
+}
+
+/* Opt-in policy to respawn a runner automatically after a failed execution. */
+#[derive(Clone, Copy, Debug)]
+pub struct RespawnPolicy {
+    /* number of restart attempts per failed execution */
+    pub max_retries: u32,
+
+    /* delay before the first attempt (doubled after every failed attempt) */
+    pub backoff: Duration,
+}
+
 #[derive(Clone, Debug)]
 pub enum QemuNyxRole {
     /* Standalone mode, snapshot is kept in memory and not serialized. */
@@ -247,6 +404,23 @@ pub struct RuntimeConfig {
 
     /* aux_buffer size */
     aux_buffer_size: usize,
+
+    /* deadline for QEMU-Nyx to open its control socket after being spawned */
+    startup_timeout: Duration,
+
+    /* host-side watchdog for QEMU-Nyx requests as a multiple of the configured timeout (0 -> disabled) */
+    host_timeout_factor: u32,
+
+    /* respawn the runner automatically after a failed execution (disabled if None) */
+    respawn_policy: Option<RespawnPolicy>,
+
+    /* backing and base directory of the shm buffers */
+    shm_backing: ShmBacking,
+    shm_base_dir: String,
+
+    /* shm work dirs are named nyx_<namespace>_<pid>_<tid>; only orphans of the same namespace are removed.
+     * If None, the namespace is derived from the workdir path (i.e. one namespace per campaign). */
+    shm_namespace: Option<String>,
 }
 
 impl RuntimeConfig{
@@ -258,6 +432,12 @@ impl RuntimeConfig{
             debug_mode: false,
             worker_id: 0,
             aux_buffer_size: DEFAULT_AUX_BUFFER_SIZE,
+            startup_timeout: DEFAULT_STARTUP_TIMEOUT,
+            host_timeout_factor: DEFAULT_HOST_TIMEOUT_FACTOR,
+            respawn_policy: None,
+            shm_backing: ShmBacking::Tmpfs,
+            shm_base_dir: DEFAULT_SHM_BASE_DIR.to_string(),
+            shm_namespace: None,
         }
     }
 
@@ -321,6 +501,58 @@ impl RuntimeConfig{
     pub fn aux_buffer_size(&self) -> usize {
         self.aux_buffer_size
     }
+
+    pub fn startup_timeout(&self) -> Duration {
+        self.startup_timeout
+    }
+
+    pub fn set_startup_timeout(&mut self, timeout: Duration){
+        self.startup_timeout = timeout;
+    }
+
+    pub fn host_timeout_factor(&self) -> u32 {
+        self.host_timeout_factor
+    }
+
+    pub fn set_host_timeout_factor(&mut self, factor: u32){
+        self.host_timeout_factor = factor;
+    }
+
+    pub fn respawn_policy(&self) -> Option<RespawnPolicy> {
+        self.respawn_policy
+    }
+
+    pub fn set_respawn_policy(&mut self, policy: Option<RespawnPolicy>){
+        self.respawn_policy = policy;
+    }
+
+    pub fn shm_backing(&self) -> ShmBacking {
+        self.shm_backing
+    }
+
+    pub fn shm_base_dir(&self) -> &str {
+        &self.shm_base_dir
+    }
+
+    pub fn set_shm_backing(&mut self, backing: ShmBacking, base_dir: Option<String>){
+        self.shm_backing = backing;
+        if let Some(base_dir) = base_dir {
+            self.shm_base_dir = base_dir;
+        }
+    }
+
+    pub fn shm_namespace(&self) -> Option<String> {
+        self.shm_namespace.clone()
+    }
+
+    /* the namespace becomes part of a path and is therefore restricted to [a-zA-Z0-9-] */
+    pub fn set_shm_namespace(&mut self, namespace: &str) -> bool{
+        if namespace.is_empty() || !namespace.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return false;
+        }
+        self.shm_namespace = Some(namespace.to_string());
+        true
+    }
     
 }
 
@@ -332,46 +564,49 @@ pub struct Config {
 }
 
 impl Config{
-    pub fn new_from_loader(sharedir: &str, default_config_folder: &str, default: ConfigLoader, config: ConfigLoader) -> Self{
-        Self{
-            runner: FuzzRunnerConfig::new_from_loader(&default_config_folder, default.runner, config.runner),
-            fuzz:  FuzzerConfig::new_from_loader(&sharedir, default.fuzz, config.fuzz),
+    pub fn new_from_loader(sharedir: &str, default_config_folder: &str, default: ConfigLoader, config: ConfigLoader) -> Result<Self, ConfigError> {
+        Ok(Self{
+            runner: FuzzRunnerConfig::new_from_loader(&default_config_folder, default.runner, config.runner)?,
+            fuzz:  FuzzerConfig::new_from_loader(&sharedir, default.fuzz, config.fuzz)?,
             runtime: RuntimeConfig::new(),
-        }
+        })
     }
 
-    pub fn new_from_sharedir(sharedir: &str) -> Result<Self, String> {
+    pub fn new_from_sharedir(sharedir: &str) -> Result<Self, ConfigError> {
         let path_to_config = format!("{}/config.ron", sharedir);
 
         let cfg_file = match File::open(&path_to_config){
             Ok(x) => {x},
-            Err(_) => return Err(format!("file or folder not found ({})!", path_to_config)),
+            Err(x) => return Err(ConfigError::FileNotFound{ path: path_to_config, source: x }),
         }; 
 
         let mut cfg: ConfigLoader = match ron::de::from_reader(cfg_file){
             Ok(x) => {x},
-            Err(x) => return Err(format!("invalid configuration ({})!", x)),
+            Err(x) => return Err(ConfigError::parse(&path_to_config, x)),
         };
 
         let include_default_config_path = match cfg.include_default_config_path{
             Some(x) => {x},
-            None => return Err(format!("no path to default configuration given!")),
+            None => return Err(ConfigError::MissingField("include_default_config_path")),
         };
 
-        let default_path = into_absolute_path(sharedir, include_default_config_path);
-        let default_config_folder = Path::new(&default_path).parent().unwrap().to_str().unwrap();
+        let default_path = into_absolute_path(sharedir, include_default_config_path)?;
+        let default_config_folder = match Path::new(&default_path).parent() {
+            Some(x) => x.to_string_lossy().to_string(),
+            None => return Err(ConfigError::InvalidValue{ field: "include_default_config_path", msg: format!("{} has no parent directory", default_path) }),
+        };
         cfg.include_default_config_path = Some(default_path.clone());
 
         let default_file = match File::open(default_path.clone()){
             Ok(x) => x,
-            Err(_) => return Err(format!("default config not found ({})!", default_path)),
+            Err(x) => return Err(ConfigError::FileNotFound{ path: default_path, source: x }),
         };
          
         let default: ConfigLoader = match ron::de::from_reader(default_file){
             Ok(x) => {x},
-            Err(x) => return Err(format!("invalid default configuration ({})!", x)),
+            Err(x) => return Err(ConfigError::parse(&default_path, x)),
         };
 
-        Ok(Self::new_from_loader(&sharedir, &default_config_folder, default, cfg))
+        Self::new_from_loader(&sharedir, &default_config_folder, default, cfg)
     }
 }
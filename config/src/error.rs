@@ -0,0 +1,56 @@
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /* config file (or default config file) cannot be opened */
+    FileNotFound { path: String, source: io::Error },
+
+    /* config file is not valid RON (line and column are 1-based, 0 if unknown) */
+    Parse { path: String, line: usize, col: usize, msg: String },
+
+    /* neither the config nor the default config specifies a required field */
+    MissingField(&'static str),
+
+    /* a relative path cannot be resolved */
+    UnresolvedPath { path: String, source: io::Error },
+
+    /* config and default config specify different runner kinds */
+    ConflictingRunners { default: &'static str, config: &'static str },
+
+    /* a field is present but its value is not usable */
+    InvalidValue { field: &'static str, msg: String },
+}
+
+impl ConfigError {
+    pub(crate) fn parse(path: &str, err: ron::Error) -> Self {
+        ConfigError::Parse {
+            path: path.to_string(),
+            line: err.position.line,
+            col: err.position.col,
+            msg: err.code.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::FileNotFound { path, source } => write!(f, "file or folder not found ({}): {}", path, source),
+            ConfigError::Parse { path, line, col, msg } => write!(f, "invalid configuration ({}:{}:{}): {}", path, line, col, msg),
+            ConfigError::MissingField(field) => write!(f, "no {} specified", field),
+            ConfigError::UnresolvedPath { path, source } => write!(f, "cannot resolve path ({}): {}", path, source),
+            ConfigError::ConflictingRunners { default, config } => write!(f, "conflicting FuzzRunner configs (default: {}, config: {})", default, config),
+            ConfigError::InvalidValue { field, msg } => write!(f, "invalid value for {}: {}", field, msg),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::FileNotFound { source, .. } | ConfigError::UnresolvedPath { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
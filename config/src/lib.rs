@@ -5,4 +5,10 @@ extern crate libc;
 
 mod loader;
 mod config;
-pub use config::*;
\ No newline at end of file
+mod error;
+pub use config::*;
+pub use error::ConfigError;
+
+#[cfg(test)]
+#[path = "tests/tests.rs"]
+mod tests;
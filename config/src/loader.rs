@@ -32,6 +32,17 @@ pub struct ForkServerConfigLoader {
 pub enum FuzzRunnerConfigLoader {
     QemuKernel(QemuKernelConfigLoader),
     QemuSnapshot(QemuSnapshotConfigLoader),
+    ForkServer(ForkServerConfigLoader),
+}
+
+impl FuzzRunnerConfigLoader {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FuzzRunnerConfigLoader::QemuKernel(_) => "QemuKernel",
+            FuzzRunnerConfigLoader::QemuSnapshot(_) => "QemuSnapshot",
+            FuzzRunnerConfigLoader::ForkServer(_) => "ForkServer",
+        }
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -51,6 +62,10 @@ pub struct FuzzerConfigLoader {
     #[serde(default = "default_ipt_filter")]
     pub ip3: IptFilter,
 
+    /* symbolic IP filters (e.g. [text_of("target_bin"), symbol_range("parse_*")]) */
+    #[serde(default)]
+    pub ipt_filters: Vec<IptFilterSpec>,
+
     pub workdir_path: Option<String>,
     pub bitmap_size: Option<usize>,
 
@@ -0,0 +1,109 @@
+use std::fs;
+
+use crate::*;
+
+fn test_sharedir(name: &str) -> String {
+    let sharedir = format!("{}/config_test_{}_{}", std::env::temp_dir().to_str().unwrap(), std::process::id(), name);
+    let _ = fs::remove_dir_all(&sharedir);
+    fs::create_dir_all(&sharedir).unwrap();
+    sharedir
+}
+
+const FUZZ_DEFAULTS: &str = concat!(
+    "fuzz: (workdir_path: Some(\"/tmp/workdir\"), bitmap_size: Some(65536), mem_limit: Some(512), time_limit: Some((secs: 0, nanos: 200000000)),\n",
+    "       seed_path: Some(\"\"), dict: Some([]), snapshot_placement: Some(none))",
+);
+
+#[test]
+fn forkserver_config() {
+    let sharedir = test_sharedir("forkserver_config");
+    fs::write(format!("{}/target", sharedir), "").unwrap();
+    fs::write(format!("{}/default.ron", sharedir), format!(
+        "(runner: ForkServer((args: None, hide_output: Some(true), input_size: Some(4096), env: Some([\"A=1\"]))),\n {})\n", FUZZ_DEFAULTS,
+    )).unwrap();
+    fs::write(format!("{}/config.ron", sharedir), concat!(
+        "(include_default_config_path: Some(\"default.ron\"),\n",
+        " runner: ForkServer((args: Some([\"target\", \"-f\", \"@@\"]), hide_output: None, input_size: None, env: Some([\"B=2\"]))),\n",
+        " fuzz: ())\n",
+    )).unwrap();
+
+    let config = Config::new_from_sharedir(&sharedir).unwrap();
+    match config.runner {
+        FuzzRunnerConfig::ForkServer(x) => {
+            /* the target binary is resolved relative to the default config, other args are passed as is */
+            assert_eq!(x.args, vec![format!("{}/target", fs::canonicalize(&sharedir).unwrap().to_str().unwrap()), "-f".to_string(), "@@".to_string()]);
+            assert!(x.hide_output);
+            assert_eq!(x.input_size, 4096);
+            assert_eq!(x.env, vec!["B=2".to_string()]);
+        },
+        x => panic!("unexpected runner config: {:?}", x),
+    }
+    assert_eq!(config.fuzz.time_limit, std::time::Duration::from_millis(200));
+
+    fs::remove_dir_all(&sharedir).unwrap();
+}
+
+#[test]
+fn missing_field() {
+    let sharedir = test_sharedir("missing_field");
+    fs::write(format!("{}/default.ron", sharedir), format!(
+        "(runner: ForkServer((args: Some([\"/bin/true\"]), hide_output: None, input_size: None, env: None)),\n {})\n", FUZZ_DEFAULTS,
+    )).unwrap();
+    fs::write(format!("{}/config.ron", sharedir), "(include_default_config_path: Some(\"default.ron\"), runner: ForkServer(()), fuzz: ())\n").unwrap();
+
+    let err = Config::new_from_sharedir(&sharedir).unwrap_err();
+    assert!(matches!(err, ConfigError::MissingField("input_size")), "{:?}", err);
+    assert_eq!(err.to_string(), "no input_size specified");
+
+    /* the config itself has to point to the default config */
+    fs::write(format!("{}/config.ron", sharedir), "(runner: ForkServer(()), fuzz: ())\n").unwrap();
+    assert!(matches!(Config::new_from_sharedir(&sharedir), Err(ConfigError::MissingField("include_default_config_path"))));
+
+    fs::remove_dir_all(&sharedir).unwrap();
+}
+
+#[test]
+fn conflicting_runners() {
+    let sharedir = test_sharedir("conflicting_runners");
+    fs::write(format!("{}/default.ron", sharedir), format!(
+        "(runner: QemuKernel((qemu_binary: Some(\"/bin/true\"), kernel: Some(\"/bin/true\"), ramfs: Some(\"/bin/true\"), debug: Some(false))),\n {})\n", FUZZ_DEFAULTS,
+    )).unwrap();
+    fs::write(format!("{}/config.ron", sharedir), "(include_default_config_path: Some(\"default.ron\"), runner: ForkServer(()), fuzz: ())\n").unwrap();
+
+    let err = Config::new_from_sharedir(&sharedir).unwrap_err();
+    assert!(matches!(err, ConfigError::ConflictingRunners{ default: "QemuKernel", config: "ForkServer" }), "{:?}", err);
+    assert_eq!(err.to_string(), "conflicting FuzzRunner configs (default: QemuKernel, config: ForkServer)");
+
+    fs::remove_dir_all(&sharedir).unwrap();
+}
+
+#[test]
+fn parse_error() {
+    let sharedir = test_sharedir("parse_error");
+    let path = format!("{}/config.ron", sharedir);
+    fs::write(&path, "(\n    include_default_config_path: Some(\"default.ron\"),\n    runner: ForkServer(()),\n    fuzz: (bitmap_size: Some(\"big\")),\n)\n").unwrap();
+
+    match Config::new_from_sharedir(&sharedir).unwrap_err() {
+        ConfigError::Parse { path: x, line, col, .. } => {
+            assert_eq!(x, path);
+            assert_eq!(line, 4);
+            assert!(col > 1, "{}", col);
+        },
+        x => panic!("unexpected error: {:?}", x),
+    }
+    let err = Config::new_from_sharedir(&sharedir).unwrap_err().to_string();
+    assert!(err.starts_with(&format!("invalid configuration ({}:4:", path)), "{}", err);
+
+    /* errors in the default config report the default config */
+    fs::write(&path, "(include_default_config_path: Some(\"default.ron\"), runner: ForkServer(()), fuzz: ())\n").unwrap();
+    fs::write(format!("{}/default.ron", sharedir), "(runner: ForkServer(()),\n fuzz: ()").unwrap();
+    match Config::new_from_sharedir(&sharedir).unwrap_err() {
+        ConfigError::Parse { path, line, .. } => {
+            assert!(path.ends_with("/default.ron"), "{}", path);
+            assert_eq!(line, 2);
+        },
+        x => panic!("unexpected error: {:?}", x),
+    }
+
+    fs::remove_dir_all(&sharedir).unwrap();
+}
@@ -0,0 +1,82 @@
+use std::fmt;
+use std::io;
+use std::process::ExitStatus;
+
+#[derive(Debug)]
+pub enum NyxError {
+    /* the QEMU-Nyx (or target) process cannot be spawned */
+    QemuSpawnFailed(io::Error),
+
+    /* QEMU-Nyx did not open the control socket in time */
+    ControlSocketTimeout,
+
+    /* the guest did not finish booting (reach the root snapshot) before the startup deadline */
+    BootTimeout,
+
+    /* QEMU-Nyx has terminated (exit code or signal and the last lines of its output) */
+    QemuExited { status: ExitStatus, output: Vec<String> },
+
+    /* the aux buffer is invalid or was created by an incompatible QEMU-Nyx version */
+    AuxBufferMismatch(String),
+
+    /* the agent has called abort() */
+    AgentAbort(String),
+
+    /* QEMU-Nyx did not answer within the host-side watchdog timeout (the process has been killed) */
+    HostTimeout,
+
+    /* QEMU-Nyx has reported an unknown exec result code */
+    UnknownExecCode(u8),
+
+    /* the shm files (bitmap, ijon, input) or their mappings cannot be set up */
+    ShmSetupFailed(io::Error),
+
+    /* communication with QEMU-Nyx has failed (usually because the process has died) */
+    Io(io::Error),
+
+    /* the config cannot be used with the selected backend */
+    InvalidConfig(String),
+
+    /* the runner has already been shut down and cannot be used (or restarted) anymore */
+    Terminated,
+}
+
+impl fmt::Display for NyxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NyxError::QemuSpawnFailed(x) => write!(f, "failed to spawn process: {}", x),
+            NyxError::ControlSocketTimeout => write!(f, "QEMU-Nyx did not open the control socket in time"),
+            NyxError::BootTimeout => write!(f, "QEMU-Nyx did not finish booting in time"),
+            NyxError::QemuExited { status, output } => {
+                write!(f, "QEMU-Nyx has terminated ({})", status)?;
+                for line in output.iter() {
+                    write!(f, "\n  {}", line)?;
+                }
+                Ok(())
+            },
+            NyxError::AuxBufferMismatch(x) => write!(f, "{}", x),
+            NyxError::AgentAbort(x) => write!(f, "agent abort() -> {}", x),
+            NyxError::HostTimeout => write!(f, "QEMU-Nyx did not respond in time (host watchdog)"),
+            NyxError::UnknownExecCode(x) => write!(f, "unknown Nyx exec result code: {}", x),
+            NyxError::ShmSetupFailed(x) => write!(f, "failed to set up shm buffers: {}", x),
+            NyxError::Io(x) => write!(f, "QEMU-Nyx I/O error: {}", x),
+            NyxError::InvalidConfig(x) => write!(f, "invalid config: {}", x),
+            NyxError::Terminated => write!(f, "the runner has already been shut down"),
+        }
+    }
+}
+
+impl std::error::Error for NyxError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NyxError::QemuSpawnFailed(x) | NyxError::ShmSetupFailed(x) | NyxError::Io(x) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for NyxError {
+    fn from(err: io::Error) -> Self {
+        NyxError::Io(err)
+    }
+}
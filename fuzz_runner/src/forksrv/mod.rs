@@ -0,0 +1,437 @@
+/* Native AFL-style forkserver backend.
+ *
+ * The target is compiled with AFL-compatible instrumentation and spawned as a
+ * regular process. The coverage bitmap is shared via SysV shm (__AFL_SHM_ID) and
+ * the forkserver is controlled via the classic FORKSRV_FD / FORKSRV_FD+1 pipe
+ * protocol. Inputs are passed either via a file (if "@@" is part of the args) or
+ * via stdin. To stay compatible with the QEMU-Nyx backend, results are reported
+ * through an aux buffer stored in the workdir.
+ */
+
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+use nix::fcntl::OFlag;
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::sys::signal::{self, Signal};
+use nix::sys::wait::WaitStatus;
+use nix::unistd::{self, Pid};
+
+use crate::config::{Config, ForkServerConfig, FuzzRunnerConfig, QemuNyxRole, DEFAULT_IJON_BUFFER_SIZE};
+use crate::error::NyxError;
+use crate::exitreason::ExitReason;
+use crate::nyx::aux_buffer::AuxBuffer;
+use crate::nyx::aux_buffer::{AUX_MAGIC, QEMU_PT_HASH, QEMU_PT_VERSION};
+use crate::nyx::aux_buffer::{NYX_SUCCESS, NYX_CRASH, NYX_TIMEOUT};
+use crate::nyx::qemu_process::QemuProcess;
+use crate::runner::FuzzRunner;
+
+#[cfg(test)]
+#[path = "tests/tests.rs"]
+mod tests;
+
+const FORKSRV_FD: RawFd = 198;
+const SHM_ENV_VAR: &str = "__AFL_SHM_ID";
+const FORKSRV_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct ForkServer {
+    process: Child,
+    cfg: ForkServerConfig,
+    input_path: String,
+
+    /* control pipe (-> FORKSRV_FD) and status pipe (<- FORKSRV_FD+1) */
+    ctl_pipe: File,
+    st_pipe: File,
+
+    aux: AuxBuffer,
+    shm_id: i32,
+    bitmap: &'static mut [u8],
+    payload: Vec<u8>,
+    ijon_buffer: Vec<u8>,
+    input_file: File,
+
+    time_limit: Duration,
+    last_run_timed_out: bool,
+    exit_reason: ExitReason,
+    running: bool,
+}
+
+/* Prefixes an I/O error with some context while keeping its kind. */
+fn context(msg: String) -> impl FnOnce(io::Error) -> io::Error {
+    move |x| io::Error::new(x.kind(), format!("{}: {}", msg, x))
+}
+
+fn pipe() -> io::Result<(RawFd, RawFd)> {
+    unistd::pipe2(OFlag::O_CLOEXEC).map_err(io::Error::from)
+}
+
+/* a zero timeout disables the timeout (as in QEMU-Nyx), shorter ones are rounded up to 1ms */
+fn poll_timeout(timeout: Duration) -> i32 {
+    match timeout.is_zero() {
+        true => -1,
+        false => std::cmp::min(timeout.as_nanos().div_ceil(1_000_000), i32::MAX as u128) as i32,
+    }
+}
+
+fn read_u32(pipe: &mut File, timeout: Duration) -> io::Result<u32> {
+    let mut buf = [0_u8; 4];
+    let mut fds = [PollFd::new(pipe.as_raw_fd(), PollFlags::POLLIN)];
+    loop {
+        match poll(&mut fds, poll_timeout(timeout)) {
+            Ok(0) => return Err(io::Error::new(io::ErrorKind::TimedOut, "forkserver did not respond in time")),
+            Ok(_) => break,
+            Err(nix::errno::Errno::EINTR) => continue,
+            Err(x) => return Err(io::Error::from(x)),
+        }
+    }
+    pipe.read_exact(&mut buf)?;
+    Ok(u32::from_ne_bytes(buf))
+}
+
+fn make_shm_bitmap(size: usize) -> io::Result<(i32, &'static mut [u8])> {
+    unsafe {
+        let shm_id = libc::shmget(libc::IPC_PRIVATE, size, libc::IPC_CREAT | libc::IPC_EXCL | 0o600);
+        if shm_id < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let ptr = libc::shmat(shm_id, std::ptr::null(), 0);
+        if ptr as isize == -1 {
+            let err = io::Error::last_os_error();
+            libc::shmctl(shm_id, libc::IPC_RMID, std::ptr::null_mut());
+            return Err(err);
+        }
+        Ok((shm_id, std::slice::from_raw_parts_mut(ptr as *mut u8, size)))
+    }
+}
+
+impl ForkServer {
+
+    pub fn new(cfg: &Config) -> Result<ForkServer, NyxError> {
+        let fs_cfg = match &cfg.runner {
+            FuzzRunnerConfig::ForkServer(x) => x.clone(),
+            _ => return Err(NyxError::InvalidConfig("ForkServer requires a ForkServer runner config".to_string())),
+        };
+
+        let workdir = &cfg.fuzz.workdir_path;
+        let worker_id = cfg.runtime.worker_id();
+
+        match cfg.runtime.process_role() {
+            QemuNyxRole::StandAlone | QemuNyxRole::Parent => {
+                QemuProcess::prepare_workdir(workdir, cfg.fuzz.seed_path.clone());
+            },
+            QemuNyxRole::Child => {
+                fs::create_dir_all(workdir).map_err(context(format!("cannot create workdir {}", workdir)))?;
+            },
+        }
+
+        let aux = Self::make_aux_buffer(&format!("{}/aux_buffer_{}", workdir, worker_id), cfg.runtime.aux_buffer_size())
+            .map_err(context("cannot create aux buffer".to_string()))
+            .map_err(NyxError::ShmSetupFailed)?;
+
+        let input_path = format!("{}/forkserver_input_{}", workdir, worker_id);
+        let input_file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(true)
+            .open(&input_path)
+            .map_err(context(format!("cannot create input file {}", input_path)))
+            .map_err(NyxError::ShmSetupFailed)?;
+
+        let (shm_id, bitmap) = make_shm_bitmap(cfg.fuzz.bitmap_size)
+            .map_err(context("cannot create shm bitmap".to_string()))
+            .map_err(NyxError::ShmSetupFailed)?;
+
+        let mut forkserver = match Self::spawn_target(&fs_cfg, shm_id, cfg.fuzz.bitmap_size, &input_path, &input_file) {
+            Ok((process, ctl_pipe, st_pipe)) => ForkServer {
+                process,
+                cfg: fs_cfg.clone(),
+                input_path,
+                ctl_pipe,
+                st_pipe,
+                aux,
+                shm_id,
+                bitmap,
+                payload: vec![0; fs_cfg.input_size],
+                ijon_buffer: vec![0; DEFAULT_IJON_BUFFER_SIZE],
+                input_file,
+                time_limit: cfg.fuzz.time_limit,
+                last_run_timed_out: false,
+                exit_reason: ExitReason::Normal(0),
+                running: true,
+            },
+            Err(x) => {
+                unsafe {
+                    libc::shmdt(bitmap.as_ptr() as *const libc::c_void);
+                    libc::shmctl(shm_id, libc::IPC_RMID, std::ptr::null_mut());
+                }
+                return Err(NyxError::QemuSpawnFailed(context(format!("cannot spawn forkserver target {}", fs_cfg.args[0]))(x)));
+            },
+        };
+
+        if let Err(x) = forkserver.handshake() {
+            forkserver.shutdown();
+            return Err(x);
+        }
+
+        forkserver.aux.config.timeout_sec = cfg.fuzz.time_limit.as_secs() as u8;
+        forkserver.aux.config.timeout_usec = cfg.fuzz.time_limit.subsec_micros();
+
+        println!("[!] libnyx: forkserver #{} is ready:", worker_id);
+        Ok(forkserver)
+    }
+
+    fn make_aux_buffer(path: &str, size: usize) -> io::Result<AuxBuffer> {
+        let aux_shm_f = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
+        aux_shm_f.set_len(size as u64)?;
+
+        let aux = AuxBuffer::new(aux_shm_f, size);
+        aux.header.magic = AUX_MAGIC;
+        aux.header.version = QEMU_PT_VERSION;
+        aux.header.hash = QEMU_PT_HASH;
+
+        /* the target is compiled with AFL-style instrumentation */
+        aux.cap.agent_trace_bitmap = 1;
+        aux.result.state = 3;
+        Ok(aux)
+    }
+
+    fn spawn_target(fs_cfg: &ForkServerConfig, shm_id: i32, bitmap_size: usize, input_path: &str, input_file: &File) -> io::Result<(Child, File, File)> {
+        let args: Vec<String> = fs_cfg.args.iter().map(|arg| arg.replace("@@", input_path)).collect();
+        let input_via_file = fs_cfg.args.iter().any(|arg| arg.contains("@@"));
+
+        let mut cmd = Command::new(&args[0]);
+        cmd.args(&args[1..]);
+        cmd.env(SHM_ENV_VAR, shm_id.to_string());
+        cmd.env("AFL_MAP_SIZE", bitmap_size.to_string());
+
+        for var in fs_cfg.env.iter() {
+            match var.split_once('=') {
+                Some((key, value)) => { cmd.env(key, value); },
+                None => { cmd.env(var, ""); },
+            }
+        }
+
+        if input_via_file {
+            cmd.stdin(Stdio::null());
+        } else {
+            /* all forked children share the file offset, which is rewound before every exec */
+            cmd.stdin(Stdio::from(input_file.try_clone()?));
+        }
+
+        if fs_cfg.hide_output {
+            cmd.stdout(Stdio::null());
+            cmd.stderr(Stdio::null());
+        }
+
+        let (ctl_read, ctl_write) = pipe()?;
+        let (st_read, st_write) = pipe()?;
+
+        unsafe {
+            cmd.pre_exec(move || {
+                if libc::dup2(ctl_read, FORKSRV_FD) < 0 || libc::dup2(st_write, FORKSRV_FD + 1) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        let spawned = cmd.spawn();
+
+        /* close the child's ends of both pipes */
+        unsafe {
+            drop(File::from_raw_fd(ctl_read));
+            drop(File::from_raw_fd(st_write));
+        }
+        let ctl_pipe = unsafe { File::from_raw_fd(ctl_write) };
+        let st_pipe = unsafe { File::from_raw_fd(st_read) };
+
+        Ok((spawned?, ctl_pipe, st_pipe))
+    }
+
+    /* the forkserver announces itself with a 4 byte hello message */
+    fn handshake(&mut self) -> Result<(), NyxError> {
+        read_u32(&mut self.st_pipe, FORKSRV_HANDSHAKE_TIMEOUT)
+            .map_err(context("forkserver handshake failed (is the target instrumented?)".to_string()))?;
+        Ok(())
+    }
+
+    /* Returns the exit reason of the last execution. */
+    pub fn exit_reason(&self) -> &ExitReason {
+        &self.exit_reason
+    }
+
+    fn write_input(&mut self) -> io::Result<()> {
+        let mut size = [0_u8; 4];
+        size.copy_from_slice(&self.payload[..4]);
+        let len = std::cmp::min(u32::from_ne_bytes(size) as usize, self.payload.len() - size.len());
+
+        self.input_file.set_len(0)?;
+        self.input_file.seek(SeekFrom::Start(0))?;
+        self.input_file.write_all(&self.payload[4..4 + len])?;
+        self.input_file.seek(SeekFrom::Start(0))?;
+        Ok(())
+    }
+
+    fn run_target(&mut self) -> io::Result<ExitReason> {
+        self.ctl_pipe.write_all(&(self.last_run_timed_out as u32).to_ne_bytes())?;
+        let child_pid = read_u32(&mut self.st_pipe, FORKSRV_HANDSHAKE_TIMEOUT)? as i32;
+        if child_pid <= 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "forkserver returned an invalid child pid"));
+        }
+
+        self.last_run_timed_out = false;
+        let status = match read_u32(&mut self.st_pipe, self.time_limit) {
+            Ok(status) => status,
+            Err(x) if x.kind() == io::ErrorKind::TimedOut => {
+                let _ = signal::kill(Pid::from_raw(child_pid), Signal::SIGKILL);
+                read_u32(&mut self.st_pipe, FORKSRV_HANDSHAKE_TIMEOUT)?;
+                self.last_run_timed_out = true;
+                return Ok(ExitReason::Timeout);
+            },
+            Err(x) => return Err(x),
+        };
+
+        /* the status is reported by the target, anything but exit / signal / stop is rejected */
+        match WaitStatus::from_raw(Pid::from_raw(child_pid), status as i32) {
+            Ok(x @ (WaitStatus::Exited(..) | WaitStatus::Signaled(..) | WaitStatus::Stopped(..))) => Ok(ExitReason::from_wait_status(x)),
+            Ok(x) => Err(io::Error::new(io::ErrorKind::InvalidData, format!("forkserver returned an unexpected wait status: {:?}", x))),
+            Err(x) => Err(io::Error::from(x)),
+        }
+    }
+
+    fn set_result(&mut self, code: u8, msg: Option<String>) {
+        self.aux.misc.len = 0;
+        if let Some(msg) = msg {
+            let len = std::cmp::min(msg.len(), self.aux.misc.data.len());
+            self.aux.misc.data[..len].copy_from_slice(&msg.as_bytes()[..len]);
+            self.aux.misc.len = len as u16;
+        }
+        self.aux.result.exec_result_code = code;
+        self.aux.result.exec_done = 1;
+    }
+}
+
+impl FuzzRunner for ForkServer {
+
+    fn spawn(_sharedir: &str, cfg: &Config) -> Result<Self, NyxError> {
+        ForkServer::new(cfg)
+    }
+
+    fn input_buffer(&self) -> &[u8] {
+        &self.payload
+    }
+
+    fn input_buffer_mut(&mut self) -> &mut [u8] {
+        &mut self.payload
+    }
+
+    fn bitmap_buffer(&self) -> &[u8] {
+        self.bitmap
+    }
+
+    fn bitmap_buffer_mut(&mut self) -> &mut [u8] {
+        self.bitmap
+    }
+
+    fn ijon_buffer(&self) -> &[u8] {
+        &self.ijon_buffer
+    }
+
+    fn ijon_buffer_mut(&mut self) -> &mut [u8] {
+        &mut self.ijon_buffer
+    }
+
+    fn aux_buffer(&self) -> &AuxBuffer {
+        &self.aux
+    }
+
+    fn aux_buffer_mut(&mut self) -> &mut AuxBuffer {
+        &mut self.aux
+    }
+
+    fn set_hprintf_fd(&mut self, _fd: i32) {
+        /* hprintf is a QEMU-Nyx hypercall; native targets write to stdout / stderr directly */
+    }
+
+    fn exec(&mut self) -> Result<(), NyxError> {
+        if self.aux.config.changed != 0 {
+            self.time_limit = Duration::new(self.aux.config.timeout_sec as u64, self.aux.config.timeout_usec * 1000);
+            self.aux.config.changed = 0;
+        }
+
+        self.write_input()?;
+        self.bitmap.fill(0);
+
+        self.exit_reason = self.run_target()?;
+        match self.exit_reason.clone() {
+            ExitReason::Normal(_) => self.set_result(NYX_SUCCESS, None),
+            ExitReason::Timeout => self.set_result(NYX_TIMEOUT, None),
+            ExitReason::Signaled(sig) | ExitReason::Stopped(sig) => {
+                let msg = match Signal::try_from(sig) {
+                    Ok(sig) => format!("target terminated by {}", sig),
+                    Err(_) => format!("target terminated by signal {}", sig),
+                };
+                self.set_result(NYX_CRASH, Some(msg));
+            },
+            x => self.set_result(NYX_CRASH, Some(format!("target terminated ({})", x.name()))),
+        }
+        Ok(())
+    }
+
+    fn restart(&mut self) -> Result<(), NyxError> {
+        if !self.running {
+            return Err(NyxError::Terminated);
+        }
+
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+
+        /* the shm bitmap and the input file are reused */
+        let (process, ctl_pipe, st_pipe) = Self::spawn_target(&self.cfg, self.shm_id, self.bitmap.len(), &self.input_path, &self.input_file)
+            .map_err(context(format!("cannot spawn forkserver target {}", self.cfg.args[0])))
+            .map_err(NyxError::QemuSpawnFailed)?;
+        self.process = process;
+        self.ctl_pipe = ctl_pipe;
+        self.st_pipe = st_pipe;
+        self.last_run_timed_out = false;
+
+        self.handshake()
+    }
+
+    fn shutdown(&mut self) {
+        if !self.running {
+            return;
+        }
+        self.running = false;
+
+        println!("[!] libnyx: sending SIGKILL to forkserver process...");
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+
+        unsafe {
+            libc::shmdt(self.bitmap.as_ptr() as *const libc::c_void);
+            libc::shmctl(self.shm_id, libc::IPC_RMID, std::ptr::null_mut());
+        }
+        self.bitmap = &mut [];
+    }
+}
+
+impl Drop for ForkServer {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
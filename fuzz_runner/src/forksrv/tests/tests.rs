@@ -0,0 +1,140 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::error::NyxError;
+use crate::exitreason::ExitReason;
+use crate::forksrv::{poll_timeout, ForkServer};
+use crate::nyx::aux_buffer::{NYX_SUCCESS, NYX_CRASH, NYX_TIMEOUT};
+use crate::runner::FuzzRunner;
+
+/* Minimal forkserver peer: sends the hello message and answers every request with the pid of a
+ * short-lived child and a raw wait status which depends on the input ("crash" -> SIGSEGV,
+ * "timeout" -> never terminates on its own, "slow" -> terminates after 50ms, "continued" -> an
+ * invalid WIFCONTINUED status, otherwise exit code = input length).
+ */
+const FORKSERVER_PEER: &str = r#"#!/bin/bash
+u32() { printf "$(printf '\\%03o\\%03o\\%03o\\%03o' $(($1 & 255)) $(($1 >> 8 & 255)) $(($1 >> 16 & 255)) $(($1 >> 24 & 255)))" >&199; }
+u32 0
+while [ "$(head -c 4 <&198 | wc -c)" -eq 4 ]; do
+    input=$(cat "$1")
+    case "$input" in
+        timeout) sleep 10 & pid=$!; u32 $pid; wait $pid; u32 9 ;;
+        crash) sleep 0 & pid=$!; wait $pid; u32 $pid; u32 11 ;;
+        slow) sleep 0.05 & pid=$!; wait $pid; u32 $pid; u32 0 ;;
+        continued) sleep 0 & pid=$!; wait $pid; u32 $pid; u32 65535 ;;
+        *) sleep 0 & pid=$!; wait $pid; u32 $pid; u32 $((${#input} << 8)) ;;
+    esac
+done
+"#;
+
+fn test_workdir(name: &str) -> String {
+    let workdir = format!("{}/libnyx_test_{}_{}", std::env::temp_dir().to_str().unwrap(), std::process::id(), name);
+    let _ = fs::remove_dir_all(&workdir);
+    workdir
+}
+
+/* Writes a sharedir with a ForkServer config for the given target and time limit (in ns) and loads it. */
+fn forkserver_config(sharedir: &str, workdir: &str, target: &str, time_limit: u32) -> Config {
+    fs::create_dir_all(sharedir).unwrap();
+    fs::write(format!("{}/default.ron", sharedir), format!(concat!(
+        "(runner: ForkServer((args: None, hide_output: Some(true), input_size: Some(4096), env: None)),\n",
+        " fuzz: (workdir_path: Some(\"{}\"), bitmap_size: Some(65536), mem_limit: Some(512), time_limit: Some((secs: 0, nanos: {})),\n",
+        "        seed_path: Some(\"\"), dict: Some([]), snapshot_placement: Some(none)))\n",
+    ), workdir, time_limit)).unwrap();
+    fs::write(format!("{}/config.ron", sharedir), format!(
+        "(include_default_config_path: Some(\"default.ron\"), runner: ForkServer((args: Some([\"{}\", \"@@\"]), hide_output: None, input_size: None, env: None)), fuzz: ())\n", target,
+    )).unwrap();
+    Config::new_from_sharedir(sharedir).unwrap()
+}
+
+fn write_peer(sharedir: &str) -> String {
+    let target = format!("{}/forkserver", sharedir);
+    fs::create_dir_all(sharedir).unwrap();
+    fs::write(&target, FORKSERVER_PEER).unwrap();
+    fs::set_permissions(&target, fs::Permissions::from_mode(0o755)).unwrap();
+    target
+}
+
+fn exec(forkserver: &mut ForkServer, data: &[u8]) -> u8 {
+    let payload = forkserver.input_buffer_mut();
+    payload[..4].copy_from_slice(&(data.len() as u32).to_ne_bytes());
+    payload[4..4 + data.len()].copy_from_slice(data);
+    forkserver.exec().unwrap();
+    forkserver.aux_buffer().result.exec_result_code
+}
+
+#[test]
+fn forkserver_exec() {
+    let sharedir = test_workdir("forkserver_exec_share");
+    let workdir = test_workdir("forkserver_exec");
+    let target = write_peer(&sharedir);
+    let config = forkserver_config(&sharedir, &workdir, &target, 200_000_000);
+
+    let mut forkserver = ForkServer::new(&config).unwrap();
+    assert_eq!(forkserver.input_buffer().len(), 4096);
+    assert_eq!(forkserver.aux_buffer().result.state, 3);
+
+    assert_eq!(exec(&mut forkserver, b"hello"), NYX_SUCCESS);
+    assert_eq!(forkserver.exit_reason(), &ExitReason::Normal(5));
+
+    assert_eq!(exec(&mut forkserver, b"crash"), NYX_CRASH);
+    assert_eq!(forkserver.exit_reason(), &ExitReason::Signaled(11));
+    assert_eq!(forkserver.aux_buffer().misc.len as usize, "target terminated by SIGSEGV".len());
+
+    assert_eq!(exec(&mut forkserver, b"timeout"), NYX_TIMEOUT);
+    assert_eq!(forkserver.exit_reason(), &ExitReason::Timeout);
+
+    /* the forkserver keeps serving after a timeout */
+    assert_eq!(exec(&mut forkserver, b"hi"), NYX_SUCCESS);
+    assert_eq!(forkserver.exit_reason(), &ExitReason::Normal(2));
+
+    /* statuses other than exit / signal / stop are errors */
+    forkserver.input_buffer_mut()[..13].copy_from_slice(b"\x09\0\0\0continued");
+    assert!(matches!(forkserver.exec(), Err(NyxError::Io(_))));
+
+    forkserver.restart().unwrap();
+    assert_eq!(exec(&mut forkserver, b"again"), NYX_SUCCESS);
+
+    forkserver.shutdown();
+    assert!(matches!(forkserver.restart(), Err(NyxError::Terminated)));
+
+    drop(forkserver);
+    let _ = fs::remove_dir_all(&workdir);
+    let _ = fs::remove_dir_all(&sharedir);
+}
+
+#[test]
+fn forkserver_handshake_fails() {
+    let sharedir = test_workdir("forkserver_handshake_fails_share");
+    let workdir = test_workdir("forkserver_handshake_fails");
+
+    /* the target never announces itself */
+    let config = forkserver_config(&sharedir, &workdir, "/bin/true", 200_000_000);
+    assert!(ForkServer::new(&config).is_err());
+
+    let _ = fs::remove_dir_all(&workdir);
+    let _ = fs::remove_dir_all(&sharedir);
+}
+
+#[test]
+fn forkserver_without_time_limit() {
+    let sharedir = test_workdir("forkserver_without_time_limit_share");
+    let workdir = test_workdir("forkserver_without_time_limit");
+    let target = write_peer(&sharedir);
+
+    /* a time limit of 0 disables the timeout */
+    let mut forkserver = ForkServer::new(&forkserver_config(&sharedir, &workdir, &target, 0)).unwrap();
+    assert_eq!(exec(&mut forkserver, b"slow"), NYX_SUCCESS);
+    assert_eq!(forkserver.exit_reason(), &ExitReason::Normal(0));
+    drop(forkserver);
+
+    /* limits below 1ms are not truncated to 0 (which would time out immediately) */
+    assert_eq!(poll_timeout(Duration::ZERO), -1);
+    assert_eq!(poll_timeout(Duration::from_micros(500)), 1);
+    assert_eq!(poll_timeout(Duration::from_micros(1500)), 2);
+
+    let _ = fs::remove_dir_all(&workdir);
+    let _ = fs::remove_dir_all(&sharedir);
+}
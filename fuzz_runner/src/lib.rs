@@ -10,7 +10,14 @@ extern crate config;
 pub mod exitreason;
 pub use exitreason::ExitReason;
 
-//pub use forksrv::ForkServer;
+pub mod error;
+pub use error::NyxError;
+
+pub mod forksrv;
+pub use forksrv::ForkServer;
+
+pub mod runner;
+pub use runner::FuzzRunner;
 
 pub mod nyx;
 pub use nyx::QemuProcess;
@@ -1,7 +1,7 @@
 
 use nix::sys::mman::*;
 use std::fs::File;
-use std::os::unix::io::IntoRawFd;
+use std::os::unix::io::AsRawFd;
 use std::fmt;
 //use std::sync::atomic::compiler_fence;
 //use std::sync::atomic::Ordering;
@@ -22,9 +22,9 @@ pub const NYX_ABORT: u8         = 5;
 
 pub const AUX_BUFFER_SIZE: usize = 4096;
 
-const AUX_MAGIC: u64 = 0x54502d554d4551_u64;
-const QEMU_PT_VERSION: u16 = 3; /* let's start at 1 for the initial version using the aux buffer */
-const QEMU_PT_HASH: u16 = 84;
+pub(crate) const AUX_MAGIC: u64 = 0x54502d554d4551_u64;
+pub(crate) const QEMU_PT_VERSION: u16 = 3; /* let's start at 1 for the initial version using the aux buffer */
+pub(crate) const QEMU_PT_HASH: u16 = 84;
 
 const HEADER_SIZE: usize = 128;
 const CAP_SIZE: usize = 256;
@@ -47,6 +47,7 @@ pub struct AuxBuffer {
     pub result: &'static mut auxilary_buffer_result_s,
     pub misc: &'static mut auxilary_buffer_misc_s,
     size: usize, /* total size of the aux buffer */
+    prot: ProtFlags,
 }
 
 impl AuxBuffer {
@@ -62,7 +63,8 @@ impl AuxBuffer {
         let null_addr = std::num::NonZeroUsize::new(0);
         let aux_buffer_alloc_size = std::num::NonZeroUsize::new(size).unwrap();
         unsafe {
-            let ptr = mmap(null_addr, aux_buffer_alloc_size, prot, flags, file.into_raw_fd(), 0).unwrap();
+            /* the mapping stays valid after the file has been closed */
+            let ptr = mmap(null_addr, aux_buffer_alloc_size, prot, flags, file.as_raw_fd(), 0).unwrap();
             let header = (ptr.add(HEADER_OFFSET) as *mut auxilary_buffer_header_s)
                 .as_mut()
                 .unwrap();
@@ -85,10 +87,23 @@ impl AuxBuffer {
                 result,
                 misc,
                 size,
+                prot,
             };
         }
     }
 
+    /* Maps the given aux buffer file at the address of this buffer (MAP_FIXED), so pointers to
+     * the aux buffer remain valid if QEMU-Nyx has been restarted.
+     */
+    pub fn remap(&self, file: &File) -> nix::Result<()> {
+        let addr = std::num::NonZeroUsize::new(self.header as *const auxilary_buffer_header_s as usize);
+        let aux_buffer_alloc_size = std::num::NonZeroUsize::new(self.size).unwrap();
+        unsafe {
+            mmap(addr, aux_buffer_alloc_size, self.prot, MapFlags::MAP_SHARED | MapFlags::MAP_FIXED, file.as_raw_fd(), 0)?;
+        }
+        Ok(())
+    }
+
     pub fn new(file: File, size: usize) -> Self {
         return AuxBuffer::new_readonly(file, false, size);
     }
@@ -124,6 +139,14 @@ impl AuxBuffer {
         Ok(())
     }
 }
+impl Drop for AuxBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = munmap(self.header as *mut auxilary_buffer_header_s as *mut std::ffi::c_void, self.size);
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 #[repr(C, packed(1))]
 pub struct auxilary_buffer_header_s {
@@ -18,6 +18,10 @@ pub struct SharedFeedbackData{
     pub ijon: IjonData,
 }
 
+/* The ijon buffer starts with SharedFeedbackData; agents write at most the slots of IjonData.max_data. */
+pub const IJON_MAX_DATA_OFFSET: usize = std::mem::offset_of!(SharedFeedbackData, ijon);
+pub const IJON_MAX_SLOTS: usize = std::mem::size_of::<IjonData>() / std::mem::size_of::<u64>();
+
 pub struct FeedbackBuffer {
     pub shared: &'static mut SharedFeedbackData,
 }
@@ -26,4 +30,9 @@ impl FeedbackBuffer{
     pub fn new(shared: &'static mut SharedFeedbackData) -> Self{
         Self{shared}
     }
+
+    /* raw view of the shared feedback data (used to unmap the buffer) */
+    pub(crate) fn as_bytes_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.shared as *mut SharedFeedbackData as *mut u8, std::mem::size_of::<SharedFeedbackData>()) }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,452 @@
+/* In-process stand-in for QEMU-Nyx.
+ *
+ * The mock creates the aux buffer file, listens on the control socket and speaks
+ * the same byte-sized request / response protocol as QEMU-Nyx. Every round trip
+ * is answered with the next scripted MockResponse (NYX_SUCCESS if the script is
+ * empty), which allows the QemuProcess / NyxProcess stack to be tested on
+ * machines without KVM-Nyx.
+ */
+
+use std::collections::VecDeque;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::prelude::*;
+use std::os::unix::fs::FileExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::nyx::aux_buffer::AuxBuffer;
+use crate::nyx::aux_buffer::{AUX_BUFFER_SIZE, AUX_MAGIC, QEMU_PT_HASH, QEMU_PT_VERSION};
+use crate::nyx::aux_buffer::{NYX_SUCCESS, NYX_CRASH, NYX_HPRINTF, NYX_TIMEOUT, NYX_ABORT, NYX_INPUT_WRITE};
+use crate::nyx::ijon_data::IJON_MAX_DATA_OFFSET;
+use crate::nyx::mem_barrier::mem_barrier;
+use crate::nyx::params::QemuParams;
+use crate::nyx::shm;
+use crate::config::{ShmBacking, DEFAULT_IJON_BUFFER_SIZE};
+
+pub const MOCK_BITMAP_SIZE: usize = 0x10000;
+pub const MOCK_INPUT_BUFFER_SIZE: usize = 1 << 17;
+
+/* scripted answer to a single run_qemu() round trip */
+#[derive(Debug, Clone)]
+pub enum MockResponse {
+    Success,
+    Crash(String),
+    Timeout,
+    Hprintf(String),
+    Abort(String),
+    InputWrite,
+
+    /* report a missing page; the following page dump request is answered implicitly */
+    PageNotFound(u64),
+
+    /* write the given (offset, value) pairs into the coverage bitmap and return NYX_SUCCESS */
+    Coverage(Vec<(usize, u8)>),
+
+    /* append the given lines to the redqueen results file and return NYX_SUCCESS */
+    Redqueen(String),
+
+    /* append the given lines to the trace results file and return NYX_SUCCESS */
+    Trace(String),
+
+    /* write the interpreter counter and the given (slot, value) ijon max pairs into the ijon buffer and return NYX_SUCCESS */
+    Ijon { executed_opcode_num: u32, max: Vec<(usize, u64)> },
+
+    /* return NYX_SUCCESS with the given execution statistics */
+    Stats { runtime_usec: u32, dirty_pages: u32, pt_trace_size: u32, bb_coverage: u32 },
+
+    /* close the control socket (simulates a crashed QEMU-Nyx process) */
+    Disconnect,
+
+    /* never answer the request (simulates a deadlocked QEMU-Nyx process) */
+    Hang,
+}
+
+/* everything the mock has observed so far */
+#[derive(Debug, Clone, Default)]
+pub struct MockLog {
+    /* number of answered exec requests (boot round trips and page dumps are not counted) */
+    pub execs: usize,
+
+    /* decoded input (u32 length prefix + data) of every exec request */
+    pub inputs: Vec<Vec<u8>>,
+
+    /* addresses of all page dump requests */
+    pub dumped_pages: Vec<u64>,
+
+    /* number of (re)connected QEMU-Nyx instances */
+    pub boots: usize,
+}
+
+pub struct MockQemuNyx {
+    workdir: String,
+    qemu_id: usize,
+    aux_buffer_size: usize,
+
+    agent_trace_bitmap: u8,
+    agent_ijon_trace_bitmap: u8,
+    agent_timeout_detection: u8,
+    redqueen: u8,
+    agent_input_buffer_size: u32,
+    agent_coverage_bitmap_size: u32,
+
+    boot: Vec<MockResponse>,
+    responses: VecDeque<MockResponse>,
+    restarts: usize,
+}
+
+pub struct MockQemuNyxHandle {
+    thread: JoinHandle<io::Result<()>>,
+    log: Arc<Mutex<MockLog>>,
+}
+
+impl MockQemuNyx {
+
+    pub fn new(workdir: &str, qemu_id: usize) -> Self {
+        Self {
+            workdir: workdir.to_string(),
+            qemu_id,
+            aux_buffer_size: AUX_BUFFER_SIZE,
+            agent_trace_bitmap: 1,
+            agent_ijon_trace_bitmap: 0,
+            agent_timeout_detection: 0,
+            redqueen: 0,
+            agent_input_buffer_size: 0,
+            agent_coverage_bitmap_size: 0,
+            boot: vec![],
+            responses: VecDeque::new(),
+            restarts: 0,
+        }
+    }
+
+    /* Returns QemuParams matching this mock instance. The spawned "QEMU" process is a
+     * plain "sleep 3600" that is killed on shutdown; the actual protocol is served by the
+     * mock thread.
+     */
+    pub fn qemu_params(&self) -> QemuParams {
+        QemuParams {
+            cmd: vec!["sleep".to_string(), "3600".to_string()],
+            restart_cmd: vec!["sleep".to_string(), "3600".to_string()],
+            qemu_aux_buffer_filename: format!("{}/aux_buffer_{}", self.workdir, self.qemu_id),
+            control_filename: format!("{}/interface_{}", self.workdir, self.qemu_id),
+            qemu_log_filename: Some(format!("{}/qemu_log_{}", self.workdir, self.qemu_id)),
+            workdir: self.workdir.clone(),
+            qemu_id: self.qemu_id,
+            bitmap_size: MOCK_BITMAP_SIZE,
+            payload_size: MOCK_INPUT_BUFFER_SIZE,
+            dump_python_code_for_inputs: false,
+            write_protected_input_buffer: false,
+            cow_primary_size: None,
+            hprintf_fd: None,
+            aux_buffer_size: self.aux_buffer_size,
+            ijon_buffer_size: DEFAULT_IJON_BUFFER_SIZE,
+            time_limit: Duration::from_millis(100),
+            startup_timeout: Duration::from_secs(5),
+            host_timeout_factor: 0,
+            shm_backing: ShmBacking::Tmpfs,
+            shm_base_dir: "/dev/shm".to_string(),
+            shm_namespace: shm::default_namespace(&self.workdir),
+        }
+    }
+
+    pub fn set_aux_buffer_size(&mut self, size: usize) {
+        self.aux_buffer_size = size;
+    }
+
+    /* 0 -> Intel-PT, 1 -> compile-time instrumentation */
+    pub fn set_agent_trace_bitmap(&mut self, value: u8) {
+        self.agent_trace_bitmap = value;
+    }
+
+    pub fn set_agent_ijon_trace_bitmap(&mut self, enable: bool) {
+        self.agent_ijon_trace_bitmap = enable as u8;
+    }
+
+    pub fn set_agent_timeout_detection(&mut self, enable: bool) {
+        self.agent_timeout_detection = enable as u8;
+    }
+
+    pub fn set_redqueen(&mut self, enable: bool) {
+        self.redqueen = enable as u8;
+    }
+
+    pub fn set_agent_input_buffer_size(&mut self, size: u32) {
+        self.agent_input_buffer_size = size;
+    }
+
+    pub fn set_agent_coverage_bitmap_size(&mut self, size: u32) {
+        self.agent_coverage_bitmap_size = size;
+    }
+
+    /* queue a response which is sent while the agent is still booting (before state 3) */
+    pub fn push_boot_response(&mut self, response: MockResponse) {
+        self.boot.push(response);
+    }
+
+    /* number of additional connections (QemuProcess::restart()) to serve after the first one */
+    pub fn set_restarts(&mut self, restarts: usize) {
+        self.restarts = restarts;
+    }
+
+    /* queue a response for the next exec request */
+    pub fn push_response(&mut self, response: MockResponse) {
+        self.responses.push_back(response);
+    }
+
+    /* Creates the aux buffer and the control socket and serves the protocol in a
+     * background thread until the host closes the connection (the last one if restarts are expected).
+     */
+    pub fn spawn(self) -> io::Result<MockQemuNyxHandle> {
+        fs::create_dir_all(&self.workdir)?;
+
+        let control_filename = format!("{}/interface_{}", self.workdir, self.qemu_id);
+        let _ = fs::remove_file(&control_filename);
+        let listener = UnixListener::bind(&control_filename)?;
+
+        let log = Arc::new(Mutex::new(MockLog::default()));
+        let thread_log = log.clone();
+        let thread = thread::spawn(move || self.serve(listener, thread_log));
+
+        Ok(MockQemuNyxHandle { thread, log })
+    }
+
+    fn create_aux_buffer(&self) -> io::Result<AuxBuffer> {
+        let aux_shm_f = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(true)
+            .open(format!("{}/aux_buffer_{}", self.workdir, self.qemu_id))?;
+        aux_shm_f.set_len(self.aux_buffer_size as u64)?;
+
+        let aux = AuxBuffer::new(aux_shm_f, self.aux_buffer_size);
+
+        aux.header.magic = AUX_MAGIC;
+        aux.header.version = QEMU_PT_VERSION;
+        aux.header.hash = QEMU_PT_HASH;
+
+        aux.cap.redqueen = self.redqueen;
+        aux.cap.agent_timeout_detection = self.agent_timeout_detection;
+        aux.cap.agent_trace_bitmap = self.agent_trace_bitmap;
+        aux.cap.agent_ijon_trace_bitmap = self.agent_ijon_trace_bitmap;
+        aux.cap.agent_input_buffer_size = self.agent_input_buffer_size;
+        aux.cap.agent_coverage_bitmap_size = self.agent_coverage_bitmap_size;
+        mem_barrier();
+
+        Ok(aux)
+    }
+
+    fn serve(mut self, listener: UnixListener, log: Arc<Mutex<MockLog>>) -> io::Result<()> {
+        for _ in 0..=self.restarts {
+            let (ctrl, _) = listener.accept()?;
+            log.lock().unwrap().boots += 1;
+            self.serve_instance(ctrl, &log)?;
+        }
+        Ok(())
+    }
+
+    fn serve_instance(&mut self, mut ctrl: UnixStream, log: &Mutex<MockLog>) -> io::Result<()> {
+        let mut aux = self.create_aux_buffer()?;
+
+        /* QEMU-Nyx resizes the shm files to the configured (or agent-requested) buffer sizes */
+        let bitmap_size = std::cmp::max(MOCK_BITMAP_SIZE, self.agent_coverage_bitmap_size as usize);
+        self.open_shm_file("bitmap")?.set_len(bitmap_size as u64)?;
+        let input_buffer_size = std::cmp::max(MOCK_INPUT_BUFFER_SIZE, self.agent_input_buffer_size as usize);
+        self.open_shm_file("payload")?.set_len(input_buffer_size as u64)?;
+
+        /* walk through the boot states; scripted boot responses are sent in state 1 */
+        let mut boot_steps = vec![(0, MockResponse::Success)];
+        boot_steps.extend(self.boot.drain(..).map(|r| (1, r)));
+        boot_steps.push((1, MockResponse::Success));
+        boot_steps.push((2, MockResponse::Success));
+        boot_steps.push((3, MockResponse::Success));
+
+        for (i, (state, response)) in boot_steps.into_iter().enumerate() {
+            if i != 0 && !Self::wait_host(&mut ctrl)? {
+                return Ok(());
+            }
+            aux.result.state = state;
+            if let MockResponse::Hang = response {
+                return Self::hang(&mut ctrl);
+            }
+            if !self.apply(&mut aux, response)? {
+                return Ok(());
+            }
+            ctrl.write_all(&[0_u8])?;
+        }
+
+        loop {
+            if !Self::wait_host(&mut ctrl)? {
+                return Ok(());
+            }
+            mem_barrier();
+
+            if aux.config.page_dump_mode != 0 {
+                let addr = aux.config.page_addr;
+                log.lock().unwrap().dumped_pages.push(addr);
+                aux.config.page_dump_mode = 0;
+                aux.result.page_not_found = 0;
+                mem_barrier();
+                ctrl.write_all(&[0_u8])?;
+                continue;
+            }
+
+            let input = self.read_input()?;
+            {
+                let mut log = log.lock().unwrap();
+                log.execs += 1;
+                log.inputs.push(input);
+            }
+
+            let response = self.responses.pop_front().unwrap_or(MockResponse::Success);
+            if let MockResponse::Hang = response {
+                return Self::hang(&mut ctrl);
+            }
+            if !self.apply(&mut aux, response)? {
+                return Ok(());
+            }
+            ctrl.write_all(&[0_u8])?;
+        }
+    }
+
+    /* Blocks until the host sends the next request. Returns false if the host has closed the connection. */
+    fn wait_host(ctrl: &mut UnixStream) -> io::Result<bool> {
+        let mut buf = [0];
+        match ctrl.read_exact(&mut buf) {
+            Ok(_) => Ok(true),
+            Err(x) if x.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+            Err(x) => Err(x),
+        }
+    }
+
+    /* Keeps the connection open without ever answering (until the host closes it). */
+    fn hang(ctrl: &mut UnixStream) -> io::Result<()> {
+        while Self::wait_host(ctrl)? {}
+        Ok(())
+    }
+
+    /* Writes the response into the aux buffer. Returns false if the connection should be closed. */
+    fn apply(&self, aux: &mut AuxBuffer, response: MockResponse) -> io::Result<bool> {
+        aux.result.page_not_found = 0;
+        aux.result.runtime_sec = 0;
+        aux.result.runtime_usec = 0;
+        aux.result.dirty_pages = 0;
+        aux.result.pt_trace_size = 0;
+        aux.result.bb_coverage = 0;
+        aux.misc.len = 0;
+
+        let (code, msg) = match response {
+            MockResponse::Success => (NYX_SUCCESS, None),
+            MockResponse::Crash(msg) => (NYX_CRASH, Some(msg)),
+            MockResponse::Timeout => (NYX_TIMEOUT, None),
+            MockResponse::Hprintf(msg) => (NYX_HPRINTF, Some(msg)),
+            MockResponse::Abort(msg) => (NYX_ABORT, Some(msg)),
+            MockResponse::InputWrite => (NYX_INPUT_WRITE, None),
+            MockResponse::PageNotFound(addr) => {
+                aux.result.page_not_found = 1;
+                aux.result.page_not_found_addr = addr;
+                (NYX_SUCCESS, None)
+            },
+            MockResponse::Coverage(entries) => {
+                let bitmap = self.open_shm_file("bitmap")?;
+                for (offset, value) in entries {
+                    bitmap.write_at(&[value], offset as u64)?;
+                }
+                (NYX_SUCCESS, None)
+            },
+            MockResponse::Redqueen(lines) => {
+                self.append_result_file("redqueen_results.txt", &lines)?;
+                (NYX_SUCCESS, None)
+            },
+            MockResponse::Trace(lines) => {
+                self.append_result_file("pt_trace_results.txt", &lines)?;
+                (NYX_SUCCESS, None)
+            },
+            MockResponse::Ijon { executed_opcode_num, max } => {
+                let ijon = self.open_shm_file("ijon")?;
+                ijon.write_at(&executed_opcode_num.to_ne_bytes(), 0)?;
+                for (slot, value) in max {
+                    ijon.write_at(&value.to_ne_bytes(), (IJON_MAX_DATA_OFFSET + slot * std::mem::size_of::<u64>()) as u64)?;
+                }
+                (NYX_SUCCESS, None)
+            },
+            MockResponse::Stats { runtime_usec, dirty_pages, pt_trace_size, bb_coverage } => {
+                aux.result.runtime_sec = runtime_usec / 1_000_000;
+                aux.result.runtime_usec = runtime_usec % 1_000_000;
+                aux.result.dirty_pages = dirty_pages;
+                aux.result.pt_trace_size = pt_trace_size;
+                aux.result.bb_coverage = bb_coverage;
+                (NYX_SUCCESS, None)
+            },
+            MockResponse::Disconnect | MockResponse::Hang => return Ok(false),
+        };
+
+        if let Some(msg) = msg {
+            let len = std::cmp::min(msg.len(), aux.misc.data.len());
+            aux.misc.data[..len].copy_from_slice(&msg.as_bytes()[..len]);
+            aux.misc.len = len as u16;
+        }
+        aux.result.exec_result_code = code;
+        aux.result.exec_done = 1;
+        mem_barrier();
+        Ok(true)
+    }
+
+    /* QEMU-Nyx appends redqueen and trace results to files in redqueen_workdir_<id> */
+    fn append_result_file(&self, name: &str, lines: &str) -> io::Result<()> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(format!("{}/redqueen_workdir_{}/{}", self.workdir, self.qemu_id, name))?
+            .write_all(lines.as_bytes())
+    }
+
+    fn open_shm_file(&self, name: &str) -> io::Result<File> {
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(format!("{}/{}_{}", self.workdir, name, self.qemu_id))
+    }
+
+    /* clears the coverage bitmap (like QEMU-Nyx does before every execution) and returns the current input */
+    fn read_input(&self) -> io::Result<Vec<u8>> {
+        let bitmap = self.open_shm_file("bitmap")?;
+        let bitmap_len = bitmap.metadata()?.len() as usize;
+        bitmap.write_at(&vec![0; bitmap_len], 0)?;
+
+        let payload = self.open_shm_file("payload")?;
+        let payload_len = payload.metadata()?.len() as usize;
+        if payload_len < std::mem::size_of::<u32>() {
+            return Ok(vec![]);
+        }
+
+        let mut size = [0_u8; 4];
+        payload.read_exact_at(&mut size, 0)?;
+        let size = std::cmp::min(u32::from_ne_bytes(size) as usize, payload_len - size.len());
+
+        let mut data = vec![0; size];
+        payload.read_exact_at(&mut data, std::mem::size_of::<u32>() as u64)?;
+        Ok(data)
+    }
+}
+
+impl MockQemuNyxHandle {
+
+    /* returns a snapshot of the current mock log */
+    pub fn log(&self) -> MockLog {
+        self.log.lock().unwrap().clone()
+    }
+
+    /* waits until the host has closed the control socket and returns the final log */
+    pub fn join(self) -> io::Result<MockLog> {
+        match self.thread.join() {
+            Ok(result) => result?,
+            Err(_) => return Err(io::Error::other("mock QEMU-Nyx thread panicked")),
+        }
+        Ok(self.log.lock().unwrap().clone())
+    }
+}
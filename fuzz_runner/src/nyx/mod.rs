@@ -1,31 +1,41 @@
 pub mod aux_buffer;
 pub mod ijon_data;
 pub mod mem_barrier;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod output;
 pub mod params;
 pub mod qemu_process;
+pub mod shm;
 
 pub use qemu_process::QemuProcess;
 
+#[cfg(test)]
+#[path = "tests/tests.rs"]
+mod tests;
+
 use std::fs;
 use std::path::PathBuf;
 
 extern crate config;
 
-fn into_absolute_path(sharedir: &str) -> String{
+use crate::error::NyxError;
+
+fn into_absolute_path(sharedir: &str) -> std::io::Result<String>{
 
     let srcdir = PathBuf::from(&sharedir);
 
     if srcdir.is_relative(){
-        return fs::canonicalize(&srcdir).unwrap().to_str().unwrap().to_string();
+        return Ok(fs::canonicalize(&srcdir)?.to_string_lossy().to_string());
     }
     else{
-        return sharedir.to_string();
+        return Ok(sharedir.to_string());
     }
 }
 
-pub fn qemu_process_new(sharedir: String, cfg: &config::Config) -> Result<QemuProcess, String> {
+pub fn qemu_process_new(sharedir: String, cfg: &config::Config) -> Result<QemuProcess, NyxError> {
 
 
-    let qemu_params = params::QemuParams::new(into_absolute_path(&sharedir), cfg);
+    let qemu_params = params::QemuParams::new(into_absolute_path(&sharedir)?, cfg)?;
     return qemu_process::QemuProcess::new(qemu_params);
 }
@@ -0,0 +1,97 @@
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/* number of lines kept in memory for error reports */
+const OUTPUT_TAIL_LINES: usize = 32;
+
+/* Collects the output (stdout and stderr) of a QEMU-Nyx process.
+ * Every captured stream is drained by a background thread (so that QEMU-Nyx never blocks
+ * on a full pipe) and written to the log file (or to our own stderr if there is none).
+ * The last lines are kept in a ring buffer to be reported if QEMU-Nyx dies.
+ */
+pub struct OutputLog {
+    lines: Arc<Mutex<VecDeque<String>>>,
+    log_file: Option<File>,
+    readers: Vec<thread::JoinHandle<()>>,
+}
+
+impl OutputLog {
+
+    /* Opens the log file (output of a previous instance is kept if append is set). */
+    pub fn new(log_filename: Option<&str>, append: bool) -> io::Result<OutputLog> {
+        let log_file = match log_filename {
+            Some(path) => Some(OpenOptions::new()
+                .create(true)
+                .write(true)
+                .append(append)
+                .truncate(!append)
+                .open(path)?),
+            None => None,
+        };
+
+        Ok(OutputLog {
+            lines: Arc::new(Mutex::new(VecDeque::with_capacity(OUTPUT_TAIL_LINES))),
+            log_file,
+            readers: vec![],
+        })
+    }
+
+    pub fn capture<R: Read + Send + 'static>(&mut self, stream: R) -> io::Result<()> {
+        let shared_lines = self.lines.clone();
+        let mut log_file = match self.log_file {
+            Some(ref f) => Some(f.try_clone()?),
+            None => None,
+        };
+
+        self.readers.push(thread::spawn(move || {
+            let mut stream = BufReader::new(stream);
+            let mut line = vec![];
+            loop {
+                line.clear();
+                match stream.read_until(b'\n', &mut line) {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => {},
+                }
+
+                let _ = match log_file {
+                    Some(ref mut f) => f.write_all(&line),
+                    None => io::stderr().write_all(&line),
+                };
+
+                let mut lines = shared_lines.lock().unwrap();
+                if lines.len() == OUTPUT_TAIL_LINES {
+                    lines.pop_front();
+                }
+                lines.push_back(String::from_utf8_lossy(&line).trim_end().to_string());
+            }
+        }));
+        Ok(())
+    }
+
+    /* Waits (at most for the given duration) until all captured streams have been closed.
+     * Used once the process has exited to make sure its last words are part of the tail.
+     */
+    pub fn wait_for_eof(&mut self, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        while self.readers.iter().any(|r| !r.is_finished()) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let (finished, running): (Vec<_>, Vec<_>) = self.readers.drain(..).partition(|r| r.is_finished());
+        for reader in finished {
+            let _ = reader.join();
+        }
+        self.readers = running;
+    }
+
+    /* Returns the last lines of the captured output (oldest line first). */
+    pub fn tail(&self) -> Vec<String> {
+        self.lines.lock().unwrap().iter().cloned().collect()
+    }
+}
@@ -1,10 +1,22 @@
 use std::time::Duration;
-use crate::{config::{Config, FuzzRunnerConfig, QemuNyxRole}, QemuProcess};
+use crate::{config::{Config, FuzzRunnerConfig, QemuNyxRole, ShmBacking, DEFAULT_IJON_BUFFER_SIZE}, QemuProcess};
+use crate::nyx::shm;
+use crate::NyxError;
+
+fn unsupported_runner() -> NyxError {
+    NyxError::InvalidConfig("QemuParams cannot be created for a ForkServer runner config".to_string())
+}
 
 pub struct QemuParams {
     pub cmd: Vec<String>,
+
+    /* command used to restart QEMU-Nyx (loads the root snapshot from the workdir if it has been serialized) */
+    pub restart_cmd: Vec<String>,
     pub qemu_aux_buffer_filename: String,
     pub control_filename: String,
+
+    /* QEMU-Nyx stdout / stderr are written to this file (None -> stdout is not captured) */
+    pub qemu_log_filename: Option<String>,
     pub workdir: String,
     pub qemu_id: usize,
     pub bitmap_size: usize,
@@ -16,12 +28,20 @@ pub struct QemuParams {
     pub hprintf_fd: Option<i32>,
 
     pub aux_buffer_size: usize,
+    pub ijon_buffer_size: usize,
     pub time_limit: Duration,
+    pub startup_timeout: Duration,
+    pub host_timeout_factor: u32,
+
+    /* the shm work dir is <shm_base_dir>/nyx_<shm_namespace>_<pid>_<tid> (unused for memfds) */
+    pub shm_backing: ShmBacking,
+    pub shm_base_dir: String,
+    pub shm_namespace: String,
 }
 
 impl QemuParams {
 
-    pub fn new(sharedir: String, fuzzer_config: &Config) -> QemuParams {
+    pub fn new(sharedir: String, fuzzer_config: &Config) -> Result<QemuParams, NyxError> {
 
         let mut cmd = vec![];
         let qemu_id =  fuzzer_config.runtime.worker_id();
@@ -32,6 +52,7 @@ impl QemuParams {
 
         let qemu_aux_buffer_filename = format!("{}/aux_buffer_{}", workdir, qemu_id);
         let control_filename = format!("{}/interface_{}", workdir, qemu_id);
+        let qemu_log_filename = if debug { None } else { Some(format!("{}/qemu_log_{}", workdir, qemu_id)) };
 
         match fuzzer_config.runner.clone(){
             FuzzRunnerConfig::QemuKernel(x) => {
@@ -50,6 +71,7 @@ impl QemuParams {
                 cmd.push("-drive".to_string());
                 cmd.push(format!("file={},index=0,media=disk", x.hda.to_string()));
             },
+            FuzzRunnerConfig::ForkServer(_) => return Err(unsupported_runner()),
         }
 
         /* generic QEMU-Nyx parameters */
@@ -72,6 +94,7 @@ impl QemuParams {
                 FuzzRunnerConfig::QemuSnapshot(_) => {
                     cmd.push("stdio".to_string());
                 }
+                FuzzRunnerConfig::ForkServer(_) => return Err(unsupported_runner()),
             }
         }
 
@@ -103,7 +126,8 @@ impl QemuParams {
 
         let mut i = 0;
         for filter in fuzzer_config.fuzz.ipt_filters{
-            if filter.a != 0 && filter.b != 0 {
+            /* unused filters are 0-0 */
+            if filter.a < filter.b {
                 nyx_ops += &format!(",ip{}_a={},ip{}_b={}", i, filter.a, i, filter.b);
             i += 1;
             }
@@ -166,6 +190,17 @@ impl QemuParams {
                         },
                     };
                 },
+                FuzzRunnerConfig::ForkServer(_) => return Err(unsupported_runner()),
+            }
+        }
+
+        /* StandAlone instances do not serialize the root snapshot and have to boot again */
+        let mut restart_cmd = cmd.clone();
+        if fuzzer_config.runtime.reuse_root_snapshot_path().is_none() {
+            if let QemuNyxRole::Parent = fuzzer_config.runtime.process_role() {
+                let pos = restart_cmd.iter().position(|arg| arg == "-fast_vm_reload")
+                    .ok_or_else(|| NyxError::InvalidConfig("QEMU-Nyx command line lacks -fast_vm_reload".to_string()))?;
+                restart_cmd[pos + 1] = format!("path={}/snapshot/,load=on", workdir);
             }
         }
 
@@ -180,10 +215,12 @@ impl QemuParams {
         };
 
 
-        return QemuParams {
+        Ok(QemuParams {
             cmd,
+            restart_cmd,
             qemu_aux_buffer_filename,
             control_filename,
+            qemu_log_filename,
             workdir: workdir.to_string(),
             qemu_id,
             bitmap_size: fuzzer_config.fuzz.bitmap_size,
@@ -196,8 +233,14 @@ impl QemuParams {
             cow_primary_size: fuzzer_config.fuzz.cow_primary_size,
             hprintf_fd: fuzzer_config.runtime.hprintf_fd(),
             aux_buffer_size: fuzzer_config.runtime.aux_buffer_size(),
-            time_limit: fuzzer_config.fuzz.time_limit
-        }
+            ijon_buffer_size: DEFAULT_IJON_BUFFER_SIZE,
+            time_limit: fuzzer_config.fuzz.time_limit,
+            startup_timeout: fuzzer_config.runtime.startup_timeout(),
+            host_timeout_factor: fuzzer_config.runtime.host_timeout_factor(),
+            shm_backing: fuzzer_config.runtime.shm_backing(),
+            shm_base_dir: fuzzer_config.runtime.shm_base_dir().to_string(),
+            shm_namespace: fuzzer_config.runtime.shm_namespace().unwrap_or_else(|| shm::default_namespace(workdir)),
+        })
     }
 
 }
@@ -1,22 +1,21 @@
 use std::os::unix::prelude::FromRawFd;
-use std::path::PathBuf;
 use nix::sys::mman::*;
 use std::fs;
 use std::io;
 use std::fs::{File, OpenOptions};
 use std::io::prelude::*;
-use std::os::unix::fs::symlink;
-use std::os::unix::io::IntoRawFd;
-use std::os::unix::io::AsRawFd;
+use std::mem::ManuallyDrop;
+use std::os::unix::io::{AsRawFd, RawFd};
 use std::os::unix::net::UnixStream;
 use std::path::Path;
 use std::process::Child;
+use std::process::Stdio;
 use std::process::Command;
 use std::{thread, time};
-use std::process;
-use fs4::FileExt;
+use timeout_readwrite::TimeoutReader;
 
-use nix::unistd::gettid;
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
 
 use std::str;
 
@@ -30,7 +29,20 @@ use crate::nyx::aux_buffer::{NYX_SUCCESS, NYX_CRASH, NYX_HPRINTF, NYX_TIMEOUT, N
 
 use crate::nyx::ijon_data::{SharedFeedbackData, FeedbackBuffer};
 use crate::nyx::mem_barrier::mem_barrier;
+use crate::nyx::output::OutputLog;
 use crate::nyx::params::QemuParams;
+use crate::nyx::shm::ShmWorkDir;
+use crate::runner::FuzzRunner;
+use crate::error::NyxError;
+
+/* lower bound for the host-side watchdog (see host_timeout()) */
+const HOST_TIMEOUT_MIN: time::Duration = time::Duration::from_secs(1);
+
+/* time QEMU-Nyx gets to handle SIGTERM before it is killed (see terminate()) */
+const QEMU_TERM_GRACE: time::Duration = time::Duration::from_secs(2);
+
+/* time QEMU-Nyx gets to terminate after closing the control socket (see exit_error()) */
+const QEMU_EXIT_GRACE: time::Duration = time::Duration::from_secs(1);
 
 pub struct QemuProcess {
 
@@ -47,11 +59,15 @@ pub struct QemuProcess {
     pub input_buffer_size: usize,
     pub payload: &'static mut [u8],
     pub params: QemuParams,
-    shm_work_dir: PathBuf,
-    #[allow(unused)]
-    shm_file_lock: File,
+    shm_work_dir: ShmWorkDir,
 
     hprintf_file: Option<File>,
+
+    /* captured QEMU-Nyx output (log file + last lines) */
+    output: OutputLog,
+
+    /* set by shutdown() (the process is gone and the shm work dir has been removed) */
+    terminated: bool,
 }
 
 fn execute_qemu(ctrl: &mut UnixStream) -> io::Result<()>{
@@ -59,169 +75,278 @@ fn execute_qemu(ctrl: &mut UnixStream) -> io::Result<()>{
     Ok(())
 }
 
-fn wait_qemu(ctrl: &mut UnixStream) -> io::Result<()>{
+/* borrowed control socket (TimeoutReader requires AsRawFd, which &UnixStream does not implement) */
+struct CtrlRef<'a>(&'a UnixStream);
+
+impl Read for CtrlRef<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl AsRawFd for CtrlRef<'_> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+/* waits for QEMU-Nyx to finish the current request (returns a TimedOut error if the watchdog fires) */
+fn wait_qemu(ctrl: &mut UnixStream, timeout: Option<time::Duration>) -> io::Result<()>{
     let mut buf = [0];
-    ctrl.read_exact(&mut buf)?;
+    TimeoutReader::new(CtrlRef(ctrl), timeout).read_exact(&mut buf)?;
     Ok(())
 }
 
-fn run_qemu(ctrl: &mut UnixStream) -> io::Result<()>{
+fn run_qemu(ctrl: &mut UnixStream, timeout: Option<time::Duration>) -> io::Result<()>{
     execute_qemu(ctrl)?;
-    wait_qemu(ctrl)?;
+    wait_qemu(ctrl, timeout)?;
     Ok(())
 }
 
-fn make_shared_data(file: &File, size: usize) -> &'static mut [u8] {
+/* boot round trip which has to finish before the startup deadline */
+fn boot_qemu(ctrl: &mut UnixStream, startup_deadline: time::Instant, run: bool) -> Result<(), NyxError> {
+    let remaining = startup_deadline.saturating_duration_since(time::Instant::now());
+    if remaining.is_zero() {
+        return Err(NyxError::BootTimeout);
+    }
+    let result = match run {
+        true => run_qemu(ctrl, Some(remaining)),
+        false => wait_qemu(ctrl, Some(remaining)),
+    };
+    match result {
+        Ok(()) => Ok(()),
+        Err(x) if x.kind() == io::ErrorKind::TimedOut => Err(NyxError::BootTimeout),
+        Err(x) => Err(NyxError::Io(x)),
+    }
+}
+
+fn make_shared_data(file: &File, size: usize) -> io::Result<&'static mut [u8]> {
     let prot = ProtFlags::PROT_READ | ProtFlags::PROT_WRITE;
     let flags = MapFlags::MAP_SHARED;
     let null_addr = std::num::NonZeroUsize::new(0);
-    let wrapped_size = std::num::NonZeroUsize::new(size).unwrap();
+    let wrapped_size = match std::num::NonZeroUsize::new(size) {
+        Some(x) => x,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "cannot map an empty shm buffer")),
+    };
     unsafe {
-        let ptr = mmap(null_addr, wrapped_size, prot, flags, file.as_raw_fd(), 0).unwrap();
+        let ptr = mmap(null_addr, wrapped_size, prot, flags, file.as_raw_fd(), 0)?;
 
         let data = std::slice::from_raw_parts_mut(ptr as *mut u8, size);
-        return data;
+        return Ok(data);
     }
 }
 
-fn make_shared_ijon_data(file: File, size: usize) -> FeedbackBuffer {
+fn make_shared_ijon_data(file: &File, size: usize) -> io::Result<FeedbackBuffer> {
     let prot = ProtFlags::PROT_READ | ProtFlags::PROT_WRITE;
     let flags = MapFlags::MAP_SHARED;
     let null_addr = std::num::NonZeroUsize::new(0);
-    let wrapped_size = std::num::NonZeroUsize::new(size).unwrap();
+    let wrapped_size = match std::num::NonZeroUsize::new(size) {
+        Some(x) => x,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "cannot map an empty shm buffer")),
+    };
     unsafe {
-        let ptr = mmap(null_addr, wrapped_size, prot, flags, file.into_raw_fd(), 0).unwrap();
-        FeedbackBuffer::new((ptr as *mut SharedFeedbackData).as_mut().unwrap())
+        let ptr = mmap(null_addr, wrapped_size, prot, flags, file.as_raw_fd(), 0)?;
+        Ok(FeedbackBuffer::new(&mut *(ptr as *mut SharedFeedbackData)))
+    }
+}
+
+/* page_size: page size of the shm backing (hugetlbfs mappings can only be unmapped as a whole) */
+fn unmap_shared_data(data: &mut [u8], page_size: usize) {
+    if !data.is_empty() {
+        unsafe {
+            let len = data.len().div_ceil(page_size) * page_size;
+            let _ = munmap(data.as_mut_ptr() as *mut std::ffi::c_void, len);
+        }
+    }
+}
+
+/* gets rid of the QEMU-Nyx process if anything goes wrong during the handshake */
+fn abort_start(child: &mut Child, output: &mut OutputLog, err: NyxError) -> NyxError {
+    if let Ok(Some(status)) = child.try_wait() {
+        /* QEMU-Nyx has died on its own -> that's the more useful error */
+        output.wait_for_eof(time::Duration::from_millis(100));
+        return NyxError::QemuExited { status, output: output.tail() };
     }
+    let _ = child.kill();
+    let _ = child.wait();
+    err
 }
 
 impl QemuProcess {
 
-    pub fn new(params: QemuParams) -> Result<QemuProcess, String> {
-        Self::prepare_redqueen_workdir(&params.workdir, params.qemu_id);
+    pub fn new(params: QemuParams) -> Result<QemuProcess, NyxError> {
+        Self::prepare_redqueen_workdir(&params.workdir, params.qemu_id)?;
 
         if params.qemu_id == 0{
             println!("[!] libnyx: spawning qemu with:\n {}", params.cmd.join(" "));
         }
 
-        let (shm_work_dir, file_lock) = Self::create_shm_work_dir();
-        let mut shm_work_dir_path = PathBuf::from(&shm_work_dir);
+        let mut shm_work_dir = ShmWorkDir::create(params.shm_backing, &params.shm_base_dir, &params.shm_namespace)
+            .map_err(NyxError::ShmSetupFailed)?;
 
-        shm_work_dir_path.push("bitmap");
+        let (bitmap_shm_f, payload_shm_f, mut bitmap_shared, mut payload_shared, ijon_shared, ijon_feedback_buffer) = match (|| -> io::Result<_> {
+            let bitmap_shm_f = shm_work_dir.create_file("bitmap", &params.workdir, &format!("bitmap_{}", params.qemu_id))?;
+            let ijon_buffer_shm_f = shm_work_dir.create_file("ijon", &params.workdir, &format!("ijon_{}", params.qemu_id))?;
+            let mut payload_shm_f = shm_work_dir.create_file("input", &params.workdir, &format!("payload_{}", params.qemu_id))?;
 
-        let bitmap_shm_f = OpenOptions::new()
-            .create(true)
-            .read(true)
-            .write(true)
-            .open(&shm_work_dir_path)
-            .expect("couldn't open bitmap file");
-
-
-        if Path::new(&format!("{}/bitmap_{}", params.workdir, params.qemu_id)).exists(){
-            fs::remove_file(format!("{}/bitmap_{}", params.workdir, params.qemu_id)).unwrap();
-        }
+            if shm_work_dir.supports_write() {
+                payload_shm_f.write_all(b"not_init")?;
+            }
+            shm_work_dir.set_len(&bitmap_shm_f, params.bitmap_size)?;
+            shm_work_dir.set_len(&payload_shm_f, params.payload_size)?;
+            shm_work_dir.set_len(&ijon_buffer_shm_f, params.ijon_buffer_size)?;
+
+            let bitmap_shared = make_shared_data(&bitmap_shm_f, params.bitmap_size)?;
+            let payload_shared = make_shared_data(&payload_shm_f, params.payload_size)?;
+
+            let ijon_shared = make_shared_data(&ijon_buffer_shm_f, params.ijon_buffer_size)?;
+            let ijon_feedback_buffer = make_shared_ijon_data(&ijon_buffer_shm_f, std::mem::size_of::<SharedFeedbackData>())?;
+            Ok((bitmap_shm_f, payload_shm_f, bitmap_shared, payload_shared, ijon_shared, ijon_feedback_buffer))
+        })() {
+            Ok(x) => x,
+            Err(x) => {
+                shm_work_dir.remove();
+                return Err(NyxError::ShmSetupFailed(x));
+            },
+        };
 
-        symlink(
-            &shm_work_dir_path,
-            format!("{}/bitmap_{}", params.workdir, params.qemu_id),
-        )
-        .unwrap();
+        /* hprintf_fd still belongs to the caller if startup fails (error paths must not close it) */
+        let mut hprintf_file = ManuallyDrop::new(params.hprintf_fd.map(|fd| unsafe { File::from_raw_fd(fd) }));
+
+        /* release all shm resources if QEMU-Nyx cannot be brought up */
+        let cleanup = |shm_work_dir: &mut ShmWorkDir, bitmap: &mut [u8], payload: &mut [u8], ijon: &mut [u8], mut feedback: FeedbackBuffer| {
+            let page_size = shm_work_dir.page_size();
+            unmap_shared_data(bitmap, page_size);
+            unmap_shared_data(payload, page_size);
+            unmap_shared_data(ijon, page_size);
+            unmap_shared_data(feedback.as_bytes_mut(), page_size);
+            shm_work_dir.remove();
+        };
 
-        shm_work_dir_path.pop();
-        shm_work_dir_path.push("ijon");
+        let (mut child, control, aux_buffer, mut output) = match Self::start_qemu(&params, &params.cmd, &mut hprintf_file, false) {
+            Ok(x) => x,
+            Err(x) => {
+                cleanup(&mut shm_work_dir, bitmap_shared, payload_shared, ijon_shared, ijon_feedback_buffer);
+                return Err(x);
+            },
+        };
 
-        let ijon_buffer_shm_f = OpenOptions::new()
-            .create(true)
-            .read(true)
-            .write(true)
-            .open(&shm_work_dir_path)
-            .expect("couldn't open bitmap file");
+        let remap = (|| -> io::Result<(usize, usize)> {
+            let mut bitmap_size = params.bitmap_size as usize;
+            //println!("[!] libnyx: {:x}", aux_buffer.cap.agent_coverage_bitmap_size);
+            if aux_buffer.cap.agent_coverage_bitmap_size != 0 {
+                //let file_len = bitmap_shm_f.metadata().unwrap().len();
+                bitmap_size = aux_buffer.cap.agent_coverage_bitmap_size as usize;
+                if aux_buffer.cap.agent_coverage_bitmap_size as usize > bitmap_shared.len(){
+                    //println!("[!] libnyx: agent requests a differnt coverage bitmap size: {:x} (current: {:x})", aux_buffer.cap.agent_coverage_bitmap_size as u32, file_len);
+                    let new_bitmap = make_shared_data(&bitmap_shm_f, aux_buffer.cap.agent_coverage_bitmap_size as usize)?;
+                    unmap_shared_data(std::mem::replace(&mut bitmap_shared, new_bitmap), shm_work_dir.page_size());
+                }
+            }
 
+            let mut input_buffer_size = params.payload_size as usize;
+            if aux_buffer.cap.agent_input_buffer_size != 0 {
+                input_buffer_size = aux_buffer.cap.agent_input_buffer_size as usize;
+                if aux_buffer.cap.agent_input_buffer_size as usize > payload_shared.len(){
+                    let new_payload = make_shared_data(&payload_shm_f, aux_buffer.cap.agent_input_buffer_size as usize)?;
+                    unmap_shared_data(std::mem::replace(&mut payload_shared, new_payload), shm_work_dir.page_size());
+                }
+            }
+            Ok((bitmap_size, input_buffer_size))
+        })();
 
-        if Path::new(&format!("{}/ijon_{}", params.workdir, params.qemu_id)).exists(){
-            fs::remove_file(format!("{}/ijon_{}", params.workdir, params.qemu_id)).unwrap();
-        }
+        let (bitmap_size, input_buffer_size) = match remap {
+            Ok(x) => x,
+            Err(x) => {
+                let err = abort_start(&mut child, &mut output, NyxError::ShmSetupFailed(x));
+                cleanup(&mut shm_work_dir, bitmap_shared, payload_shared, ijon_shared, ijon_feedback_buffer);
+                return Err(err);
+            },
+        };
 
-        symlink(
-            &shm_work_dir_path,
-            format!("{}/ijon_{}", params.workdir, params.qemu_id),
-        )
-        .unwrap();
+        println!("[!] libnyx: qemu #{} is ready:", params.qemu_id);
 
-        shm_work_dir_path.pop();
-        shm_work_dir_path.push("input");
+        aux_buffer.config.reload_mode = 1;
+        aux_buffer.config.timeout_sec = params.time_limit.as_secs() as u8;
+        aux_buffer.config.timeout_usec = params.time_limit.subsec_micros();
+        aux_buffer.config.changed = 1;
 
-        let mut payload_shm_f = OpenOptions::new()
-            .create(true)
-            .read(true)
-            .write(true)
-            .open(&shm_work_dir_path)
-            .expect("couldn't open payload file");
+        return Ok(QemuProcess {
+            process: child,
+            aux: aux_buffer,
+            feedback_data: ijon_feedback_buffer,
+            ijon_buffer: ijon_shared,
+            ctrl: control,
+            bitmap: bitmap_shared,
+            bitmap_size: bitmap_size,
+            input_buffer_size: input_buffer_size,
+            payload: payload_shared,
+            params,
+            shm_work_dir,
+            hprintf_file: ManuallyDrop::into_inner(hprintf_file),
+            output,
+            terminated: false,
+        });
+    }
 
-        if Path::new(&format!("{}/payload_{}", params.workdir, params.qemu_id)).exists(){
-            fs::remove_file(format!("{}/payload_{}", params.workdir, params.qemu_id)).unwrap();
+    /* Spawns QEMU-Nyx, connects to its control socket and waits until the agent is ready (boot state 3). */
+    fn start_qemu(params: &QemuParams, qemu_cmd: &[String], hprintf_file: &mut Option<File>, restart: bool) -> Result<(Child, UnixStream, AuxBuffer, OutputLog), NyxError> {
+        let mut cmd = Command::new(&qemu_cmd[0]);
+        cmd.args(&qemu_cmd[1..]);
+        if params.dump_python_code_for_inputs{
+            cmd.env("DUMP_PAYLOAD_MODE", "TRUE");
         }
 
-        symlink(
-            &shm_work_dir_path,
-            format!("{}/payload_{}", params.workdir, params.qemu_id),
-        )
-        .unwrap();
-
-        payload_shm_f.write_all(b"not_init").unwrap();
-        bitmap_shm_f.set_len(params.bitmap_size as u64).unwrap();
-        ijon_buffer_shm_f.set_len(0x1000).unwrap();
-
-        let mut bitmap_shared = make_shared_data(&bitmap_shm_f, params.bitmap_size);
-        let mut payload_shared = make_shared_data(&payload_shm_f, params.payload_size);
-
-        let ijon_shared = make_shared_data(&ijon_buffer_shm_f, 0x1000);
-        let ijon_feedback_buffer = make_shared_ijon_data(ijon_buffer_shm_f, 0x1000);
+        /* stdout stays attached to the terminal if there is no log file (-serial mon:stdio in debug mode) */
+        let mut output = OutputLog::new(params.qemu_log_filename.as_deref(), restart)?;
+        if params.qemu_log_filename.is_some() {
+            cmd.stdout(Stdio::piped());
+        }
+        cmd.stderr(Stdio::piped());
+        let mut child = cmd.spawn().map_err(NyxError::QemuSpawnFailed)?;
 
-        let mut child = if params.dump_python_code_for_inputs{
-            Command::new(&params.cmd[0])
-            .args(&params.cmd[1..])
-            .env("DUMP_PAYLOAD_MODE", "TRUE")
-            .spawn()
-            .expect("failed to execute process")            
+        let captured = [child.stdout.take().map(|x| Box::new(x) as Box<dyn Read + Send>), child.stderr.take().map(|x| Box::new(x) as Box<dyn Read + Send>)];
+        for stream in captured.into_iter().flatten() {
+            if let Err(x) = output.capture(stream) {
+                return Err(abort_start(&mut child, &mut output, NyxError::Io(x)));
+            }
         }
-        else{
-            Command::new(&params.cmd[0])
-            .args(&params.cmd[1..])
-            .spawn()
-            .expect("failed to execute process")
-        };
 
+        let startup_deadline = time::Instant::now() + params.startup_timeout;
         let mut control = loop {
             match UnixStream::connect(&params.control_filename) {
                 Ok(stream) => break stream,
                 _ => {
+                    if let Ok(Some(status)) = child.try_wait() {
+                        output.wait_for_eof(time::Duration::from_millis(100));
+                        return Err(NyxError::QemuExited { status, output: output.tail() });
+                    }
+                    if time::Instant::now() >= startup_deadline {
+                        return Err(abort_start(&mut child, &mut output, NyxError::ControlSocketTimeout));
+                    }
                     thread::sleep(time::Duration::from_millis(1))
                 },
             }
         };
 
-        if wait_qemu(&mut control).is_err() {
-            return Err(format!("cannot launch QEMU-Nyx..."));
+        if let Err(x) = boot_qemu(&mut control, startup_deadline, false) {
+            return Err(abort_start(&mut child, &mut output, x));
         }
 
         let aux_buffer = {
-            let aux_shm_f = OpenOptions::new()
+            let aux_shm_f = match OpenOptions::new()
                 .read(true)
                 .write(true)
-                .open(&params.qemu_aux_buffer_filename)
-                .expect("couldn't open aux buffer file");
+                .open(&params.qemu_aux_buffer_filename) {
+                    Ok(x) => x,
+                    Err(x) => return Err(abort_start(&mut child, &mut output, NyxError::Io(x))),
+                };
 
             AuxBuffer::new(aux_shm_f, params.aux_buffer_size)
         };
 
-        match aux_buffer.validate_header(){
-            Err(x) => {
-                child.kill().unwrap();
-                child.wait().unwrap();
-                return Err(x);
-            },
-            Ok(_) => {},
+        if let Err(x) = aux_buffer.validate_header(){
+            return Err(abort_start(&mut child, &mut output, NyxError::AuxBufferMismatch(x)));
         }
         if params.write_protected_input_buffer{
             if params.qemu_id == 0 {
@@ -231,95 +356,97 @@ impl QemuProcess {
             aux_buffer.config.changed = 1;
         }
 
-        let mut hprintf_file = match params.hprintf_fd {
-            Some(fd) =>  Some(unsafe { File::from_raw_fd(fd) }),
-            None => None,
-        }; 
-
         loop {
 
             match aux_buffer.result.exec_result_code {
                 NYX_HPRINTF     => {
                     let len = aux_buffer.misc.len;
-                    QemuProcess::output_hprintf(&mut hprintf_file, &String::from_utf8_lossy(&aux_buffer.misc.data[0..len as usize]).yellow());
+                    QemuProcess::output_hprintf(hprintf_file, &String::from_utf8_lossy(&aux_buffer.misc.data[0..len as usize]).yellow());
                 },
                 NYX_ABORT => {
                     let len = aux_buffer.misc.len;
-                    let msg = format!("agent abort() -> \n\t{}", String::from_utf8_lossy(&aux_buffer.misc.data[0..len as usize]).red());
-
-                    /* get rid of this process */
-                    child.kill().unwrap();
-                    child.wait().unwrap();
-
-                    return Err(msg);
+                    let msg = String::from_utf8_lossy(&aux_buffer.misc.data[0..len as usize]).to_string();
+                    return Err(abort_start(&mut child, &mut output, NyxError::AgentAbort(msg)));
                 }
                 NYX_SUCCESS => {},
                 x => {
-                    panic!(" -> unkown type ? {}", x);
+                    return Err(abort_start(&mut child, &mut output, NyxError::UnknownExecCode(x)));
                 }
             }
 
             if aux_buffer.result.state == 3 {
                 break;
             }
-            if run_qemu(&mut control).is_err(){
-                return Err(format!("failed to establish fuzzing loop..."));
-            } 
-        }
-
-        let mut bitmap_size = params.bitmap_size as usize;
-        //println!("[!] libnyx: {:x}", aux_buffer.cap.agent_coverage_bitmap_size);
-        if aux_buffer.cap.agent_coverage_bitmap_size != 0 {
-            //let file_len = bitmap_shm_f.metadata().unwrap().len();
-            bitmap_size = aux_buffer.cap.agent_coverage_bitmap_size as usize;
-            if aux_buffer.cap.agent_coverage_bitmap_size as usize > bitmap_shared.len(){
-                //println!("[!] libnyx: agent requests a differnt coverage bitmap size: {:x} (current: {:x})", aux_buffer.cap.agent_coverage_bitmap_size as u32, file_len);
-                bitmap_shared = make_shared_data(&bitmap_shm_f, aux_buffer.cap.agent_coverage_bitmap_size as usize);
-            }
-        }
-
-        let mut input_buffer_size = params.payload_size as usize;
-        if aux_buffer.cap.agent_input_buffer_size != 0 {
-            input_buffer_size = aux_buffer.cap.agent_input_buffer_size as usize;
-            if aux_buffer.cap.agent_input_buffer_size as usize > payload_shared.len(){
-                payload_shared = make_shared_data(&payload_shm_f, aux_buffer.cap.agent_input_buffer_size as usize);
+            if let Err(x) = boot_qemu(&mut control, startup_deadline, true) {
+                return Err(abort_start(&mut child, &mut output, x));
             }
         }
 
         match aux_buffer.cap.agent_trace_bitmap {
             0 => println!("[!] libnyx: coverage mode: Intel-PT (KVM-Nyx and libxdc)"),
             1 => println!("[!] libnyx: coverage mode: compile-time instrumentation"),
-            _ => panic!("unkown aux_buffer.cap.agent_trace_bitmap value"),
+            x => {
+                let msg = format!("unknown aux_buffer.cap.agent_trace_bitmap value: {}", x);
+                return Err(abort_start(&mut child, &mut output, NyxError::AuxBufferMismatch(msg)));
+            },
         };
 
-        println!("[!] libnyx: qemu #{} is ready:", params.qemu_id);
+        Ok((child, control, aux_buffer, output))
+    }
 
-        aux_buffer.config.reload_mode = 1;
-        aux_buffer.config.timeout_sec = params.time_limit.as_secs() as u8;
-        aux_buffer.config.timeout_usec = params.time_limit.subsec_micros();
+    /* Kills the current QEMU-Nyx process and spawns a new one (using params.restart_cmd).
+     * The shm files (bitmap, payload, ijon) and their mappings are reused, so pointers to these
+     * buffers remain valid. The new aux buffer is mapped at the address of the previous one.
+     */
+    pub fn restart(&mut self) -> Result<(), NyxError> {
+        if self.terminated {
+            return Err(NyxError::Terminated);
+        }
+
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+
+        println!("[!] libnyx: restarting qemu #{}...", self.params.qemu_id);
+        let (mut child, control, aux_buffer, mut output) = Self::start_qemu(&self.params, &self.params.restart_cmd, &mut self.hprintf_file, true)?;
+
+        /* the existing mappings cannot grow without invalidating pointers handed out to the user */
+        let bitmap_size = aux_buffer.cap.agent_coverage_bitmap_size as usize;
+        let input_buffer_size = aux_buffer.cap.agent_input_buffer_size as usize;
+        if bitmap_size > self.bitmap.len() || input_buffer_size > self.payload.len() {
+            let msg = format!("agent requests larger buffers after restart (bitmap: {:x}, input: {:x})", bitmap_size, input_buffer_size);
+            return Err(abort_start(&mut child, &mut output, NyxError::AuxBufferMismatch(msg)));
+        }
+
+        /* keep the options of the previous instance */
+        aux_buffer.config.reload_mode = self.aux.config.reload_mode;
+        aux_buffer.config.timeout_sec = self.aux.config.timeout_sec;
+        aux_buffer.config.timeout_usec = self.aux.config.timeout_usec;
         aux_buffer.config.changed = 1;
 
-        return Ok(QemuProcess {
-            process: child,
-            aux: aux_buffer,
-            feedback_data: ijon_feedback_buffer,
-            ijon_buffer: ijon_shared,
-            ctrl: control,
-            bitmap: bitmap_shared,
-            bitmap_size: bitmap_size,
-            input_buffer_size: input_buffer_size,
-            payload: payload_shared,
-            params,
-            shm_work_dir,
-            shm_file_lock: file_lock,
-            hprintf_file,
-        });
+        /* pointers to the aux buffer (see NyxProcess::aux_buffer_as_mut_ptr()) must not dangle */
+        let remapped = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&self.params.qemu_aux_buffer_filename)
+            .and_then(|file| self.aux.remap(&file).map_err(io::Error::from));
+        if let Err(x) = remapped {
+            return Err(abort_start(&mut child, &mut output, NyxError::Io(x)));
+        }
+        drop(aux_buffer);
+
+        println!("[!] libnyx: qemu #{} is ready:", self.params.qemu_id);
+
+        self.process = child;
+        self.ctrl = control;
+        self.output = output;
+        Ok(())
     }
 
     fn output_hprintf(hprintf_file: &mut Option<File>, msg: &str){
         match hprintf_file {
             Some(ref mut f) => {
-                f.write_fmt(format_args!("{}", msg)).unwrap();
+                /* a broken hprintf fd must not take down the fuzzing loop */
+                let _ = f.write_fmt(format_args!("{}", msg));
             },
             None => {
                 print!("{}", msg);
@@ -335,19 +462,66 @@ impl QemuProcess {
         &mut self.aux
     }
 
+    /* Returns the last lines QEMU-Nyx has written to stdout / stderr. */
+    pub fn output_tail(&self) -> Vec<String> {
+        self.output.tail()
+    }
+
+    /* Turns a failed request into QemuExited if QEMU-Nyx has terminated (or terminates within a grace period). */
+    fn exit_error(&mut self, err: io::Error) -> NyxError {
+        let deadline = time::Instant::now() + QEMU_EXIT_GRACE;
+        loop {
+            match self.process.try_wait() {
+                Ok(Some(status)) => {
+                    self.output.wait_for_eof(time::Duration::from_millis(100));
+                    return NyxError::QemuExited { status, output: self.output.tail() };
+                },
+                Ok(None) if time::Instant::now() < deadline => thread::sleep(time::Duration::from_millis(1)),
+                _ => return NyxError::Io(err),
+            }
+        }
+    }
+
     pub fn set_hprintf_fd(&mut self, fd: i32){
         self.hprintf_file = unsafe { Some(File::from_raw_fd(fd)) };
     }
 
-    pub fn send_payload(&mut self) -> io::Result<()>{
+    /* Returns the host-side watchdog for a single round trip (a multiple of the current timeout).
+     * There is no watchdog if it is disabled (factor 0) or if the guest timeout is disabled.
+     */
+    fn host_timeout(&self) -> Option<time::Duration> {
+        let timeout_sec = self.aux.config.timeout_sec;
+        let timeout_usec = self.aux.config.timeout_usec;
+        let timeout = time::Duration::from_secs(timeout_sec as u64) + time::Duration::from_micros(timeout_usec as u64);
+        if self.params.host_timeout_factor == 0 || timeout.is_zero() {
+            return None;
+        }
+        Some(std::cmp::max(HOST_TIMEOUT_MIN, timeout * self.params.host_timeout_factor))
+    }
+
+    /* run_qemu() guarded by the host-side watchdog. If QEMU-Nyx does not answer in time,
+     * it is considered to be stuck and gets killed (the process has to be restarted afterwards).
+     */
+    fn run_qemu_watched(&mut self) -> Result<(), NyxError> {
+        let timeout = self.host_timeout();
+        match run_qemu(&mut self.ctrl, timeout) {
+            Ok(()) => Ok(()),
+            Err(x) if x.kind() == io::ErrorKind::TimedOut => {
+                println!("[!] libnyx: QEMU-Nyx #{} did not respond within {:?} -> killing it", self.params.qemu_id, timeout.unwrap());
+                let _ = self.process.kill();
+                let _ = self.process.wait();
+                Err(NyxError::HostTimeout)
+            },
+            Err(x) => Err(self.exit_error(x)),
+        }
+    }
+
+    pub fn send_payload(&mut self) -> Result<(), NyxError>{
         let mut old_address: u64 = 0;
 
         loop {
             mem_barrier();
-            match run_qemu(&mut self.ctrl) {
-                Err(x) => return Err(x),
-                Ok(_) => {},
-            }
+            self.run_qemu_watched()?;
             mem_barrier();
 
             if self.aux.result.page_not_found != 0 {
@@ -360,10 +534,7 @@ impl QemuProcess {
                     self.aux.config.changed = 1;
 
                     mem_barrier();
-                    match run_qemu(&mut self.ctrl) {
-                        Err(x) => return Err(x),
-                        Ok(_) => {},
-                    }
+                    self.run_qemu_watched()?;
                     mem_barrier();
 
                     continue;
@@ -381,14 +552,15 @@ impl QemuProcess {
                 },
                 NYX_ABORT       => {
                     let len = self.aux.misc.len;
-                    println!("[!] libnyx: agent abort() -> \"{}\"", String::from_utf8_lossy(&self.aux.misc_data_slice()[0..len as usize]).red());
-                    break;
+                    let msg = String::from_utf8_lossy(&self.aux.misc_data_slice()[0..len as usize]).to_string();
+                    println!("[!] libnyx: agent abort() -> \"{}\"", msg.red());
+                    return Err(NyxError::AgentAbort(msg));
                 },
                 NYX_SUCCESS | NYX_CRASH | NYX_INPUT_WRITE | NYX_TIMEOUT      => {
                     break;
                 },
                 x => {
-                    panic!("[!] libnyx: ERROR -> unkown Nyx exec result code: {}", x);
+                    return Err(NyxError::UnknownExecCode(x));
                 }
             }
         }
@@ -402,30 +574,46 @@ impl QemuProcess {
     }
 
     pub fn wait(&mut self) {
-        self.process.wait().unwrap();
+        let _ = self.process.wait();
     }
 
     fn remove_shm_work_dir(&mut self){
-
         /* move originals into workdir (in case we need the data to debug stuff) */
-        let shm_path = self.shm_work_dir.to_str().unwrap();
-        fs::remove_file(&format!("{}/bitmap_{}", &self.params.workdir, self.params.qemu_id)).unwrap();
-        fs::copy(&format!("{}/bitmap", shm_path), &format!("{}/bitmap_{}", &self.params.workdir, self.params.qemu_id)).unwrap();
+        self.shm_work_dir.remove();
+    }
 
-        fs::remove_file(&format!("{}/payload_{}", &self.params.workdir, self.params.qemu_id)).unwrap();
-        fs::copy(&format!("{}/input", shm_path), &format!("{}/payload_{}", &self.params.workdir, self.params.qemu_id)).unwrap();
+    /* Asks QEMU-Nyx to terminate (SIGTERM) and sends SIGKILL if it is still alive after a grace period. */
+    fn terminate(&mut self) {
+        if let Ok(Some(_)) = self.process.try_wait() {
+            return;
+        }
 
-        fs::remove_file(&format!("{}/ijon_{}", &self.params.workdir, self.params.qemu_id)).unwrap();
-        fs::copy(&format!("{}/ijon", shm_path), &format!("{}/ijon_{}", &self.params.workdir, self.params.qemu_id)).unwrap();
+        println!("[!] libnyx: sending SIGTERM to QEMU-Nyx process...");
+        let _ = signal::kill(Pid::from_raw(self.process.id() as i32), Signal::SIGTERM);
 
-        /* remove this shm directory */
-        fs::remove_dir_all(&self.shm_work_dir).unwrap();
-    }
+        let deadline = time::Instant::now() + QEMU_TERM_GRACE;
+        while time::Instant::now() < deadline {
+            if let Ok(Some(_)) = self.process.try_wait() {
+                return;
+            }
+            thread::sleep(time::Duration::from_millis(1));
+        }
 
-    pub fn shutdown(&mut self) {
         println!("[!] libnyx: sending SIGKILL to QEMU-Nyx process...");
-        self.process.kill().unwrap();
+        let _ = self.process.kill();
         self.wait();
+    }
+
+    /* Terminates QEMU-Nyx and removes the shm work dir (safe to call multiple times).
+     * The shm buffers stay mapped until the QemuProcess object is dropped.
+     */
+    pub fn shutdown(&mut self) {
+        if self.terminated {
+            return;
+        }
+        self.terminated = true;
+
+        self.terminate();
         self.remove_shm_work_dir();
     }
 
@@ -499,76 +687,83 @@ impl QemuProcess {
         }
     }
 
-    fn prepare_redqueen_workdir(workdir: &str, qemu_id: usize) {
+    fn prepare_redqueen_workdir(workdir: &str, qemu_id: usize) -> io::Result<()> {
         fs::create_dir_all(format!("{}/redqueen_workdir_{}", workdir, qemu_id))
-            .expect("couldn't initialize workdir");   
-    }
-
-    fn remove_unused_shm_work_dirs(){
-        /* find and remove orphaned Nyx shm workdirs in /dev/shm */
-        for p in glob::glob(&format!("/dev/shm/nyx_*")).expect("couldn't glob??"){
-            let mut path = p.unwrap();
-            
-            path.push("lock");
-            if path.exists(){
-
-                let file_lock = match OpenOptions::new()
-                    .read(true)
-                    .open(&path){
-                        Err(x) => {
-                            println!("Warning: {}", x);
-                            Err(x)
-                        },
-                        x => {
-                            x
-                        },
-                    };
-
-                if file_lock.is_ok(){
-                    path.pop();
-
-                    match file_lock.unwrap().try_lock_exclusive(){
-                        Ok(_) => {
-                            if path.starts_with("/dev/shm/") {
-                                match fs::remove_dir_all(path){
-                                    Err(x) => {
-                                        println!("Warning: {}", x);
-                                    },
-                                    _ => {},
-                                }
-                            }
-                        },
-                        Err(_) => {},
-                    }
-                }
-            }
-        }
     }
 
     fn clear_workdir(workdir: &str) {
         let _ = fs::remove_dir_all(workdir);
-        Self::remove_unused_shm_work_dirs()
     }
+}
 
-    fn create_shm_work_dir() -> (PathBuf, File) {
-        let shm_work_dir_path_str = format!("/dev/shm/nyx_{}_{}/", process::id(), gettid());
-        let shm_work_dir_path = PathBuf::from(&shm_work_dir_path_str);
+impl FuzzRunner for QemuProcess {
 
-        fs::create_dir_all(&shm_work_dir_path).expect("couldn't initialize shm work directory");
+    fn spawn(sharedir: &str, cfg: &config::Config) -> Result<Self, NyxError> {
+        crate::nyx::qemu_process_new(sharedir.to_string(), cfg)
+    }
 
-        let file_lock_path_str = format!("/dev/shm/nyx_{}_{}/lock", process::id(), gettid());
-        let file_lock_path = Path::new(&file_lock_path_str);
+    fn input_buffer(&self) -> &[u8] {
+        self.payload
+    }
 
-        let file_lock = OpenOptions::new()
-            .create(true)
-            .read(true)
-            .write(true)
-            .open(file_lock_path)
-            .expect("couldn't open shm work dir lock file");
+    fn input_buffer_mut(&mut self) -> &mut [u8] {
+        self.payload
+    }
+
+    fn bitmap_buffer(&self) -> &[u8] {
+        &self.bitmap[.. self.bitmap_size]
+    }
+
+    fn bitmap_buffer_mut(&mut self) -> &mut [u8] {
+        &mut self.bitmap[.. self.bitmap_size]
+    }
+
+    fn ijon_buffer(&self) -> &[u8] {
+        self.ijon_buffer
+    }
+
+    fn ijon_buffer_mut(&mut self) -> &mut [u8] {
+        self.ijon_buffer
+    }
+
+    fn aux_buffer(&self) -> &AuxBuffer {
+        QemuProcess::aux_buffer(self)
+    }
+
+    fn aux_buffer_mut(&mut self) -> &mut AuxBuffer {
+        QemuProcess::aux_buffer_mut(self)
+    }
+
+    fn set_hprintf_fd(&mut self, fd: i32) {
+        QemuProcess::set_hprintf_fd(self, fd)
+    }
+
+    fn redqueen_workdir(&self) -> Option<String> {
+        Some(format!("{}/redqueen_workdir_{}", self.params.workdir, self.params.qemu_id))
+    }
+
+    fn exec(&mut self) -> Result<(), NyxError> {
+        self.send_payload()
+    }
+
+    fn restart(&mut self) -> Result<(), NyxError> {
+        QemuProcess::restart(self)
+    }
+
+    fn shutdown(&mut self) {
+        QemuProcess::shutdown(self)
+    }
+}
 
-        file_lock.lock_exclusive().unwrap();
+impl Drop for QemuProcess {
+    fn drop(&mut self) {
+        self.shutdown();
 
-        (shm_work_dir_path, file_lock)
+        let page_size = self.shm_work_dir.page_size();
+        unmap_shared_data(self.bitmap, page_size);
+        unmap_shared_data(self.payload, page_size);
+        unmap_shared_data(self.ijon_buffer, page_size);
+        unmap_shared_data(self.feedback_data.as_bytes_mut(), page_size);
     }
 }
 
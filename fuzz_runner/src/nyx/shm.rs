@@ -0,0 +1,187 @@
+use std::ffi::CString;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::fs::symlink;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use fs4::FileExt;
+use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+use nix::sys::statfs::statfs;
+use nix::unistd::gettid;
+
+use crate::config::ShmBacking;
+
+/* Per-instance home of the shm buffers shared with QEMU-Nyx.
+ *
+ * Tmpfs / Hugetlbfs: files in <base_dir>/nyx_<namespace>_<pid>_<tid>/. The directory contains a
+ * lock file which is held as long as the instance is alive, so that orphaned directories (left
+ * behind by crashed fuzzers) of the same namespace can be detected and removed.
+ *
+ * Memfd: anonymous memfds which are kept open by this object. QEMU-Nyx opens them via
+ * /proc/<pid>/fd/<fd> (the workdir symlinks point there).
+ */
+pub struct ShmWorkDir {
+    backing: ShmBacking,
+    dir: Option<PathBuf>,
+    #[allow(unused)]
+    lock: Option<File>,
+
+    /* workdir link, path of the shm file and the file itself (keeps the memfds alive) */
+    files: Vec<(String, PathBuf, File)>,
+    page_size: usize,
+}
+
+/* Default namespace of a campaign (FNV-1a hash of the workdir path). */
+pub fn default_namespace(workdir: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in workdir.as_bytes() {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+/* Removes shm work dirs of the given namespace whose owner has died (i.e. their lock can be taken). */
+pub fn remove_orphaned_shm_work_dirs(base_dir: &str, namespace: &str) {
+    let pattern = format!("{}/nyx_{}_*", base_dir, namespace);
+    let paths = match glob::glob(&pattern) {
+        Ok(x) => x,
+        Err(_) => return,
+    };
+
+    for path in paths.flatten() {
+        let file_lock = match File::open(path.join("lock")) {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+
+        if file_lock.try_lock_exclusive().is_ok() {
+            if let Err(x) = fs::remove_dir_all(&path) {
+                println!("Warning: {}", x);
+            }
+        }
+    }
+}
+
+impl ShmWorkDir {
+
+    pub fn create(backing: ShmBacking, base_dir: &str, namespace: &str) -> io::Result<ShmWorkDir> {
+        if backing == ShmBacking::Memfd {
+            return Ok(ShmWorkDir {
+                backing,
+                dir: None,
+                lock: None,
+                files: vec![],
+                page_size: 0x1000,
+            });
+        }
+
+        remove_orphaned_shm_work_dirs(base_dir, namespace);
+
+        /* the directory is locked under a temporary name (which does not match the pattern of
+         * remove_orphaned_shm_work_dirs()) and renamed afterwards, so other instances never see it unlocked
+         */
+        let name = format!("nyx_{}_{}_{}", namespace, process::id(), gettid());
+        let tmp_dir = PathBuf::from(format!("{}/.{}.tmp", base_dir, name));
+        let dir = PathBuf::from(format!("{}/{}", base_dir, name));
+        let _ = fs::remove_dir_all(&tmp_dir);
+        fs::create_dir_all(&tmp_dir)?;
+
+        let lock = match Self::lock_dir(&tmp_dir).and_then(|lock| fs::rename(&tmp_dir, &dir).map(|_| lock)) {
+            Ok(x) => x,
+            Err(x) => {
+                let _ = fs::remove_dir_all(&tmp_dir);
+                return Err(x);
+            },
+        };
+
+        let page_size = match backing {
+            /* hugetlbfs reports the huge page size as block size */
+            ShmBacking::Hugetlbfs => statfs(&dir).map_err(io::Error::from)?.optimal_transfer_size() as usize,
+            _ => 0x1000,
+        };
+
+        Ok(ShmWorkDir {
+            backing,
+            dir: Some(dir),
+            lock: Some(lock),
+            files: vec![],
+            page_size,
+        })
+    }
+
+    fn lock_dir(dir: &Path) -> io::Result<File> {
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(dir.join("lock"))?;
+        lock.lock_exclusive()?;
+        Ok(lock)
+    }
+
+    /* Creates a new shm file and links it into the workdir (as <workdir>/<link_name>). */
+    pub fn create_file(&mut self, name: &str, workdir: &str, link_name: &str) -> io::Result<File> {
+        let (path, file) = match self.dir {
+            Some(ref dir) => {
+                let path = dir.join(name);
+                let file = OpenOptions::new()
+                    .create(true)
+                    .truncate(false)
+                    .read(true)
+                    .write(true)
+                    .open(&path)?;
+                (path, file)
+            },
+            None => {
+                let c_name = CString::new(format!("nyx_{}", name)).unwrap();
+                let fd = memfd_create(&c_name, MemFdCreateFlag::MFD_CLOEXEC).map_err(io::Error::from)?;
+                let file = unsafe { File::from_raw_fd(fd) };
+                (PathBuf::from(format!("/proc/{}/fd/{}", process::id(), file.as_raw_fd())), file)
+            },
+        };
+
+        let link = format!("{}/{}", workdir, link_name);
+        if fs::symlink_metadata(&link).is_ok(){
+            fs::remove_file(&link)?;
+        }
+        symlink(&path, &link)?;
+
+        /* the caller gets its own fd (the stored one is referenced by the /proc path) */
+        let caller_file = file.try_clone()?;
+        self.files.push((link, path, file));
+        Ok(caller_file)
+    }
+
+    /* Resizes a shm file (hugetlbfs files can only be sized in multiples of the huge page size). */
+    pub fn set_len(&self, file: &File, size: usize) -> io::Result<()> {
+        file.set_len((size.div_ceil(self.page_size) * self.page_size) as u64)
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /* hugetlbfs files cannot be written via write(2) */
+    pub fn supports_write(&self) -> bool {
+        self.backing != ShmBacking::Hugetlbfs
+    }
+
+    /* Copies the current content of all shm files into the workdir (replacing the symlinks)
+     * and removes the shm work dir.
+     */
+    pub fn remove(&mut self) {
+        for (link, path, _) in self.files.drain(..) {
+            let _ = fs::remove_file(&link);
+            let _ = fs::copy(&path, &link);
+        }
+
+        if let Some(ref dir) = self.dir {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
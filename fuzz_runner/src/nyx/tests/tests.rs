@@ -1,8 +1,436 @@
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::io::IntoRawFd;
+use std::time::Duration;
+
+use crate::error::NyxError;
+use crate::nyx::aux_buffer::{NYX_SUCCESS, NYX_CRASH, NYX_TIMEOUT, NYX_ABORT, NYX_INPUT_WRITE};
+use crate::nyx::mock::{MockQemuNyx, MockQemuNyxHandle, MockResponse, MOCK_BITMAP_SIZE};
+use crate::nyx::qemu_process::QemuProcess;
+use crate::runner::FuzzRunner;
+use crate::config::ShmBacking;
+
+fn test_workdir(name: &str) -> String {
+    let workdir = format!("{}/libnyx_test_{}_{}", std::env::temp_dir().to_str().unwrap(), std::process::id(), name);
+    let _ = fs::remove_dir_all(&workdir);
+    workdir
+}
+
+fn spawn(mock: MockQemuNyx) -> (QemuProcess, MockQemuNyxHandle) {
+    let params = mock.qemu_params();
+    let handle = mock.spawn().unwrap();
+    let qemu = QemuProcess::new(params).unwrap();
+    (qemu, handle)
+}
+
+fn set_input(qemu: &mut QemuProcess, data: &[u8]) {
+    qemu.payload[..4].copy_from_slice(&(data.len() as u32).to_ne_bytes());
+    qemu.payload[4..4 + data.len()].copy_from_slice(data);
+}
+
+fn misc_string(qemu: &QemuProcess) -> String {
+    String::from_utf8_lossy(qemu.aux_buffer().misc.as_slice()).to_string()
+}
+
+fn teardown(mut qemu: QemuProcess, handle: MockQemuNyxHandle, workdir: &str) -> crate::nyx::mock::MockLog {
+    qemu.shutdown();
+    drop(qemu);
+    let log = handle.join().unwrap();
+    let _ = fs::remove_dir_all(workdir);
+    log
+}
+
+#[test]
+fn boot_and_exec() {
+    let workdir = test_workdir("boot_and_exec");
+    let (mut qemu, handle) = spawn(MockQemuNyx::new(&workdir, 1));
+
+    assert_eq!(qemu.aux_buffer().result.state, 3);
+    qemu.send_payload().unwrap();
+    assert_eq!(qemu.aux_buffer().result.exec_result_code, NYX_SUCCESS);
+
+    let log = teardown(qemu, handle, &workdir);
+    assert_eq!(log.execs, 1);
+}
+
 // Check hprintf works
+#[test]
+fn hprintf() {
+    let workdir = test_workdir("hprintf");
+    let mut mock = MockQemuNyx::new(&workdir, 1);
+    mock.push_boot_response(MockResponse::Hprintf("booting\n".to_string()));
+    mock.push_response(MockResponse::Hprintf("hello from the agent\n".to_string()));
+    let (mut qemu, handle) = spawn(mock);
+
+    let log_path = format!("{}/hprintf.log", workdir);
+    qemu.set_hprintf_fd(File::create(&log_path).unwrap().into_raw_fd());
+
+    qemu.send_payload().unwrap();
+    assert_eq!(qemu.aux_buffer().result.exec_result_code, NYX_SUCCESS);
+
+    let mut output = String::new();
+    File::open(&log_path).unwrap().read_to_string(&mut output).unwrap();
+    assert!(output.contains("hello from the agent"));
+
+    /* the hprintf round trip and the actual execution */
+    let log = teardown(qemu, handle, &workdir);
+    assert_eq!(log.execs, 2);
+}
+
 // Check bitmap writes work
+#[test]
+fn bitmap_writes() {
+    let workdir = test_workdir("bitmap_writes");
+    let mut mock = MockQemuNyx::new(&workdir, 1);
+    mock.push_response(MockResponse::Coverage(vec![(0, 1), (1337, 42)]));
+    let (mut qemu, handle) = spawn(mock);
+
+    qemu.send_payload().unwrap();
+    assert_eq!(qemu.bitmap[0], 1);
+    assert_eq!(qemu.bitmap[1337], 42);
+
+    /* the bitmap is reset before the next execution */
+    qemu.send_payload().unwrap();
+    assert_eq!(qemu.bitmap[1337], 0);
+
+    teardown(qemu, handle, &workdir);
+}
+
 // Check input data works
+#[test]
+fn input_data() {
+    let workdir = test_workdir("input_data");
+    let (mut qemu, handle) = spawn(MockQemuNyx::new(&workdir, 1));
+
+    set_input(&mut qemu, b"INPUT");
+    qemu.send_payload().unwrap();
+    set_input(&mut qemu, b"ANOTHER INPUT");
+    qemu.send_payload().unwrap();
+
+    let log = teardown(qemu, handle, &workdir);
+    assert_eq!(log.inputs, vec![b"INPUT".to_vec(), b"ANOTHER INPUT".to_vec()]);
+}
+
 // Check crash detection works
+#[test]
+fn crash_detection() {
+    let workdir = test_workdir("crash_detection");
+    let mut mock = MockQemuNyx::new(&workdir, 1);
+    mock.push_response(MockResponse::Crash("SIGSEGV".to_string()));
+    mock.push_response(MockResponse::InputWrite);
+    mock.push_response(MockResponse::Abort("agent gave up".to_string()));
+    let (mut qemu, handle) = spawn(mock);
+
+    qemu.send_payload().unwrap();
+    assert_eq!(qemu.aux_buffer().result.exec_result_code, NYX_CRASH);
+    assert_eq!(misc_string(&qemu), "SIGSEGV");
+
+    qemu.send_payload().unwrap();
+    assert_eq!(qemu.aux_buffer().result.exec_result_code, NYX_INPUT_WRITE);
+
+    match qemu.send_payload() {
+        Err(NyxError::AgentAbort(msg)) => assert_eq!(msg, "agent gave up"),
+        x => panic!("unexpected result: {:?}", x),
+    }
+    assert_eq!(qemu.aux_buffer().result.exec_result_code, NYX_ABORT);
+
+    teardown(qemu, handle, &workdir);
+}
+
 // Check timeouts work
+#[test]
+fn timeouts() {
+    let workdir = test_workdir("timeouts");
+    let mut mock = MockQemuNyx::new(&workdir, 1);
+    mock.push_response(MockResponse::Timeout);
+    let (mut qemu, handle) = spawn(mock);
+
+    qemu.send_payload().unwrap();
+    assert_eq!(qemu.aux_buffer().result.exec_result_code, NYX_TIMEOUT);
+    qemu.send_payload().unwrap();
+    assert_eq!(qemu.aux_buffer().result.exec_result_code, NYX_SUCCESS);
+
+    teardown(qemu, handle, &workdir);
+}
+
+#[test]
+fn page_not_found() {
+    let workdir = test_workdir("page_not_found");
+    let mut mock = MockQemuNyx::new(&workdir, 1);
+    mock.push_response(MockResponse::PageNotFound(0x7fff_0000));
+    mock.push_response(MockResponse::Crash("after page dump".to_string()));
+    let (mut qemu, handle) = spawn(mock);
+
+    qemu.send_payload().unwrap();
+    assert_eq!(qemu.aux_buffer().result.exec_result_code, NYX_CRASH);
+
+    let log = teardown(qemu, handle, &workdir);
+    assert_eq!(log.dumped_pages, vec![0x7fff_0000]);
+}
+
+#[test]
+fn agent_requested_buffer_sizes() {
+    let workdir = test_workdir("agent_requested_buffer_sizes");
+    let mut mock = MockQemuNyx::new(&workdir, 1);
+    mock.set_agent_coverage_bitmap_size((MOCK_BITMAP_SIZE * 2) as u32);
+    mock.set_agent_input_buffer_size(0x1000);
+    let (qemu, handle) = spawn(mock);
+
+    assert_eq!(qemu.bitmap_size, MOCK_BITMAP_SIZE * 2);
+    assert!(qemu.bitmap.len() >= MOCK_BITMAP_SIZE * 2);
+    assert_eq!(qemu.input_buffer_size, 0x1000);
+
+    teardown(qemu, handle, &workdir);
+}
+
+#[test]
+fn agent_abort_during_boot() {
+    let workdir = test_workdir("agent_abort_during_boot");
+    let mut mock = MockQemuNyx::new(&workdir, 1);
+    mock.push_boot_response(MockResponse::Abort("missing harness".to_string()));
+    let params = mock.qemu_params();
+    let handle = mock.spawn().unwrap();
+
+    match QemuProcess::new(params) {
+        Ok(_) => panic!("QemuProcess::new() should fail"),
+        Err(NyxError::AgentAbort(msg)) => assert_eq!(msg, "missing harness"),
+        Err(x) => panic!("unexpected error: {}", x),
+    }
+
+    handle.join().unwrap();
+    let _ = fs::remove_dir_all(&workdir);
+}
+
+#[test]
+fn qemu_died() {
+    let workdir = test_workdir("qemu_died");
+    let mut mock = MockQemuNyx::new(&workdir, 1);
+    mock.push_response(MockResponse::Disconnect);
+    let (mut qemu, handle) = spawn(mock);
+
+    assert!(matches!(qemu.send_payload(), Err(NyxError::Io(_))));
+
+    teardown(qemu, handle, &workdir);
+}
+
+#[test]
+fn host_timeout() {
+    let workdir = test_workdir("host_timeout");
+    let mut mock = MockQemuNyx::new(&workdir, 1);
+    mock.push_response(MockResponse::Hang);
+    let mut params = mock.qemu_params();
+    params.host_timeout_factor = 1;
+    let handle = mock.spawn().unwrap();
+    let mut qemu = QemuProcess::new(params).unwrap();
+
+    assert!(matches!(qemu.send_payload(), Err(NyxError::HostTimeout)));
+
+    teardown(qemu, handle, &workdir);
+}
+
+#[test]
+fn restart_keeps_buffers() {
+    let workdir = test_workdir("restart_keeps_buffers");
+    let mut mock = MockQemuNyx::new(&workdir, 1);
+    mock.set_restarts(1);
+    mock.push_response(MockResponse::Disconnect);
+    let (mut qemu, handle) = spawn(mock);
+    let bitmap_ptr = qemu.bitmap.as_ptr();
+    let payload_ptr = qemu.payload.as_ptr();
+    let aux_ptr = qemu.aux_buffer().header as *const _;
+
+    assert!(qemu.send_payload().is_err());
+    qemu.restart().unwrap();
+    assert_eq!(qemu.bitmap.as_ptr(), bitmap_ptr);
+    assert_eq!(qemu.payload.as_ptr(), payload_ptr);
+    assert_eq!(qemu.aux_buffer().header as *const _, aux_ptr);
+    assert!(qemu.aux_buffer().validate_header().is_ok());
+
+    set_input(&mut qemu, b"AFTER RESTART");
+    qemu.send_payload().unwrap();
+    assert_eq!(qemu.aux_buffer().result.exec_result_code, NYX_SUCCESS);
+
+    qemu.shutdown();
+    assert!(matches!(qemu.restart(), Err(NyxError::Terminated)));
+
+    let log = teardown(qemu, handle, &workdir);
+    assert_eq!(log.boots, 2);
+    assert_eq!(log.inputs.last().unwrap(), b"AFTER RESTART");
+}
+
+#[test]
+fn qemu_exits_during_startup() {
+    let workdir = test_workdir("qemu_exits_during_startup");
+    let mut params = MockQemuNyx::new(&workdir, 1).qemu_params();
+    fs::create_dir_all(&workdir).unwrap();
+    params.cmd = vec!["sh".to_string(), "-c".to_string(), "echo 'invalid -device nyx' >&2; exit 3".to_string()];
+
+    match QemuProcess::new(params) {
+        Err(NyxError::QemuExited { status, output }) => {
+            assert_eq!(status.code(), Some(3));
+            assert_eq!(output, vec!["invalid -device nyx".to_string()]);
+        },
+        Err(x) => panic!("unexpected error: {}", x),
+        Ok(_) => panic!("QemuProcess::new() should fail"),
+    }
+
+    let _ = fs::remove_dir_all(&workdir);
+}
+
+#[test]
+fn qemu_exits_during_exec() {
+    let workdir = test_workdir("qemu_exits_during_exec");
+    let mut mock = MockQemuNyx::new(&workdir, 1);
+    mock.push_response(MockResponse::Disconnect);
+    let mut params = mock.qemu_params();
+    params.cmd = vec!["sh".to_string(), "-c".to_string(), "echo 'KVM internal error'; sleep 0.3; exit 7".to_string()];
+    let log_filename = params.qemu_log_filename.clone().unwrap();
+    let handle = mock.spawn().unwrap();
+    let mut qemu = QemuProcess::new(params).unwrap();
+
+    match qemu.send_payload() {
+        Err(NyxError::QemuExited { status, output }) => {
+            assert_eq!(status.code(), Some(7));
+            assert_eq!(output, vec!["KVM internal error".to_string()]);
+        },
+        x => panic!("unexpected result: {:?}", x),
+    }
+    assert_eq!(fs::read_to_string(&log_filename).unwrap(), "KVM internal error\n");
+
+    teardown(qemu, handle, &workdir);
+}
+
+#[test]
+fn control_socket_timeout() {
+    let workdir = test_workdir("control_socket_timeout");
+    let mut params = MockQemuNyx::new(&workdir, 1).qemu_params();
+    fs::create_dir_all(&workdir).unwrap();
+    params.startup_timeout = Duration::from_millis(50);
+    let hprintf_fd = File::create(format!("{}/hprintf.log", workdir)).unwrap().into_raw_fd();
+    params.hprintf_fd = Some(hprintf_fd);
+
+    assert!(matches!(QemuProcess::new(params), Err(NyxError::ControlSocketTimeout)));
+
+    /* the hprintf fd still belongs to the caller after a failed startup */
+    assert!(nix::fcntl::fcntl(hprintf_fd, nix::fcntl::FcntlArg::F_GETFD).is_ok());
+    nix::unistd::close(hprintf_fd).unwrap();
+
+    let _ = fs::remove_dir_all(&workdir);
+}
+
+#[test]
+fn boot_timeout() {
+    let workdir = test_workdir("boot_timeout");
+    let mut mock = MockQemuNyx::new(&workdir, 1);
+    mock.push_boot_response(MockResponse::Hang);
+    let mut params = mock.qemu_params();
+    params.startup_timeout = Duration::from_millis(200);
+    let handle = mock.spawn().unwrap();
+
+    assert!(matches!(QemuProcess::new(params), Err(NyxError::BootTimeout)));
+
+    handle.join().unwrap();
+    let _ = fs::remove_dir_all(&workdir);
+}
+
+#[test]
+fn drop_cleans_up() {
+    let workdir = test_workdir("drop_cleans_up");
+    let (mut qemu, handle) = spawn(MockQemuNyx::new(&workdir, 1));
+    let shm_work_dir = fs::read_link(format!("{}/bitmap_1", workdir)).unwrap().parent().unwrap().to_path_buf();
+    assert!(shm_work_dir.exists());
+
+    qemu.shutdown();
+    qemu.shutdown();
+    assert!(!shm_work_dir.exists());
+    drop(qemu);
+
+    /* without an explicit shutdown */
+    let (qemu, handle2) = spawn(MockQemuNyx::new(&workdir, 2));
+    let shm_work_dir = fs::read_link(format!("{}/bitmap_2", workdir)).unwrap().parent().unwrap().to_path_buf();
+    drop(qemu);
+    assert!(!shm_work_dir.exists());
+
+    handle.join().unwrap();
+    handle2.join().unwrap();
+    let _ = fs::remove_dir_all(&workdir);
+}
+
+#[test]
+fn runner_trait_object() {
+    let workdir = test_workdir("runner_trait_object");
+    let mut mock = MockQemuNyx::new(&workdir, 1);
+    mock.push_response(MockResponse::Coverage(vec![(7, 1)]));
+    let params = mock.qemu_params();
+    let handle = mock.spawn().unwrap();
+
+    let mut runner: Box<dyn FuzzRunner> = Box::new(QemuProcess::new(params).unwrap());
+    runner.input_buffer_mut()[..4].copy_from_slice(&1_u32.to_ne_bytes());
+    runner.input_buffer_mut()[4] = b'A';
+    runner.exec().unwrap();
+
+    assert_eq!(runner.aux_buffer().result.exec_result_code, NYX_SUCCESS);
+    assert_eq!(runner.bitmap_buffer().len(), MOCK_BITMAP_SIZE);
+    assert_eq!(runner.bitmap_buffer()[7], 1);
+
+    runner.shutdown();
+    drop(runner);
+    let log = handle.join().unwrap();
+    assert_eq!(log.inputs, vec![b"A".to_vec()]);
+    let _ = fs::remove_dir_all(&workdir);
+}
+
+#[test]
+fn shm_backing() {
+    let workdir = test_workdir("shm_backing");
+    let shm_base_dir = format!("{}_shm", workdir);
+    let _ = fs::remove_dir_all(&shm_base_dir);
+
+    /* orphans of other namespaces are left alone */
+    fs::create_dir_all(format!("{}/nyx_test_1_1", shm_base_dir)).unwrap();
+    fs::create_dir_all(format!("{}/nyx_other_1_1", shm_base_dir)).unwrap();
+    File::create(format!("{}/nyx_test_1_1/lock", shm_base_dir)).unwrap();
+    File::create(format!("{}/nyx_other_1_1/lock", shm_base_dir)).unwrap();
+
+    let mock = MockQemuNyx::new(&workdir, 1);
+    let mut params = mock.qemu_params();
+    params.shm_base_dir = shm_base_dir.clone();
+    params.shm_namespace = "test".to_string();
+    let handle = mock.spawn().unwrap();
+    let qemu = QemuProcess::new(params).unwrap();
+
+    assert!(fs::read_link(format!("{}/bitmap_1", workdir)).unwrap().starts_with(&shm_base_dir));
+    assert!(!std::path::Path::new(&format!("{}/nyx_test_1_1", shm_base_dir)).exists());
+    assert!(std::path::Path::new(&format!("{}/nyx_other_1_1", shm_base_dir)).exists());
+
+    /* the work dir of a live instance is locked (and never visible under its temporary name) */
+    crate::nyx::shm::remove_orphaned_shm_work_dirs(&shm_base_dir, "test");
+    assert!(fs::metadata(fs::read_link(format!("{}/bitmap_1", workdir)).unwrap()).is_ok());
+    assert!(fs::read_dir(&shm_base_dir).unwrap().all(|x| !x.unwrap().file_name().to_string_lossy().starts_with('.')));
+    teardown(qemu, handle, &workdir);
+
+    /* memfd */
+    let mut mock = MockQemuNyx::new(&workdir, 2);
+    mock.push_response(MockResponse::Coverage(vec![(1337, 42)]));
+    let mut params = mock.qemu_params();
+    params.shm_backing = ShmBacking::Memfd;
+    let handle = mock.spawn().unwrap();
+    let mut qemu = QemuProcess::new(params).unwrap();
+
+    assert!(fs::read_link(format!("{}/bitmap_2", workdir)).unwrap().starts_with("/proc/"));
+    qemu.send_payload().unwrap();
+    assert_eq!(qemu.bitmap[1337], 42);
+
+    /* the content is preserved in the workdir */
+    qemu.shutdown();
+    assert_eq!(fs::read(format!("{}/bitmap_2", workdir)).unwrap()[1337], 42);
+
+    teardown(qemu, handle, &workdir);
+    let _ = fs::remove_dir_all(&shm_base_dir);
+}
+
+// TODO: the following checks require a real QEMU-Nyx instance
 // Check snapshot reset memory&regixters works
 // Check snapshot reset timer works
 // Check snapshot restet hdd works
@@ -10,4 +438,4 @@
 
 
 // Check that all small edit distancem utations are performed in reasonable time
-// Check that length extension is performed in reasonable time
\ No newline at end of file
+// Check that length extension is performed in reasonable time
@@ -0,0 +1,59 @@
+use crate::config::{Config, FuzzRunnerConfig};
+use crate::error::NyxError;
+use crate::nyx::aux_buffer::AuxBuffer;
+use crate::forksrv::ForkServer;
+use crate::nyx::qemu_process::QemuProcess;
+
+/* Common interface of all execution backends (QEMU-Nyx, mock backends, ...).
+ * The aux buffer is the shared channel for per-exec options and results; backends
+ * which are not backed by QEMU-Nyx have to emulate the fields they support.
+ */
+pub trait FuzzRunner {
+
+    /* Spawns a new backend instance for the given sharedir and config. */
+    fn spawn(sharedir: &str, cfg: &Config) -> Result<Self, NyxError> where Self: Sized;
+
+    /* Input buffer (u32 length prefix followed by the actual input data). */
+    fn input_buffer(&self) -> &[u8];
+    fn input_buffer_mut(&mut self) -> &mut [u8];
+
+    /* Coverage bitmap (already truncated to the negotiated bitmap size). */
+    fn bitmap_buffer(&self) -> &[u8];
+    fn bitmap_buffer_mut(&mut self) -> &mut [u8];
+
+    /* Interpreter data and ijon max slots (see nyx::ijon_data). */
+    fn ijon_buffer(&self) -> &[u8];
+    fn ijon_buffer_mut(&mut self) -> &mut [u8];
+
+    fn aux_buffer(&self) -> &AuxBuffer;
+    fn aux_buffer_mut(&mut self) -> &mut AuxBuffer;
+
+    fn set_hprintf_fd(&mut self, fd: i32);
+
+    /* Per-worker directory QEMU-Nyx writes redqueen and trace results to (None if not supported). */
+    fn redqueen_workdir(&self) -> Option<String> {
+        None
+    }
+
+    /* Runs the current input. The outcome is reported via aux_buffer().result. */
+    fn exec(&mut self) -> Result<(), NyxError>;
+
+    /* Kills the backend process and spawns a new one (e.g. after an error).
+     * Input, bitmap and ijon buffers are reused and remain valid.
+     */
+    fn restart(&mut self) -> Result<(), NyxError>;
+
+    fn shutdown(&mut self);
+}
+
+/* Spawns the backend selected by the runner config. */
+pub fn runner_new(sharedir: String, cfg: &Config) -> Result<Box<dyn FuzzRunner>, NyxError> {
+    match cfg.runner {
+        FuzzRunnerConfig::QemuKernel(_) | FuzzRunnerConfig::QemuSnapshot(_) => {
+            Ok(Box::new(QemuProcess::spawn(&sharedir, cfg)?))
+        },
+        FuzzRunnerConfig::ForkServer(_) => {
+            Ok(Box::new(ForkServer::spawn(&sharedir, cfg)?))
+        },
+    }
+}
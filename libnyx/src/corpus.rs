@@ -0,0 +1,285 @@
+/*
+    libnyx workdir corpus (inputs stored by outcome in corpus/normal, crash, kasan and timeout)
+
+    Copyright (C) 2021 Sergej Schumilo
+    This file is part of libnyx.
+
+    libnyx is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 2 of the License, or
+    (at your option) any later version.
+    libnyx is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+    You should have received a copy of the GNU General Public License
+    along with libnyx.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::fs;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::*;
+
+const INPUT_EXTENSION: &str = "bin";
+const METADATA_EXTENSION: &str = "meta";
+
+/* used to create unique names for temporary files (together with the pid) */
+static TMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NyxCorpusKind {
+    Normal,
+    Crash,
+    Kasan,
+    Timeout,
+}
+
+impl NyxCorpusKind {
+
+    pub const ALL: [NyxCorpusKind; 4] = [NyxCorpusKind::Normal, NyxCorpusKind::Crash, NyxCorpusKind::Kasan, NyxCorpusKind::Timeout];
+
+    /* name of the sub directory in <workdir>/corpus/ */
+    pub fn dir_name(&self) -> &'static str {
+        match self {
+            NyxCorpusKind::Normal => "normal",
+            NyxCorpusKind::Crash => "crash",
+            NyxCorpusKind::Kasan => "kasan",
+            NyxCorpusKind::Timeout => "timeout",
+        }
+    }
+
+    /* Returns the corpus an execution outcome belongs to (None for errors and aborts). */
+    pub fn from_return_value(value: NyxReturnValue) -> Option<NyxCorpusKind> {
+        match value {
+            NyxReturnValue::Normal => Some(NyxCorpusKind::Normal),
+            NyxReturnValue::Crash => Some(NyxCorpusKind::Crash),
+            NyxReturnValue::Asan => Some(NyxCorpusKind::Kasan),
+            NyxReturnValue::Timeout | NyxReturnValue::HostTimeout => Some(NyxCorpusKind::Timeout),
+            _ => None,
+        }
+    }
+}
+
+/* Sidecar (<hash>.meta) stored next to every corpus entry. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorpusMetadata {
+    pub worker_id: usize,
+
+    /* seconds since the unix epoch */
+    pub timestamp: u64,
+
+    pub value: NyxReturnValue,
+    pub runtime: Duration,
+    pub dirty_pages: u32,
+    pub pt_trace_size: u32,
+    pub bb_coverage: u32,
+
+    /* crash message reported by the agent (if any) */
+    pub message: Option<String>,
+}
+
+fn parse_return_value(value: &str) -> Option<NyxReturnValue> {
+    Some(match value {
+        "Normal" => NyxReturnValue::Normal,
+        "Crash" => NyxReturnValue::Crash,
+        "Asan" => NyxReturnValue::Asan,
+        "Timeout" => NyxReturnValue::Timeout,
+        "InvalidWriteToPayload" => NyxReturnValue::InvalidWriteToPayload,
+        "Error" => NyxReturnValue::Error,
+        "IoError" => NyxReturnValue::IoError,
+        "Abort" => NyxReturnValue::Abort,
+        "HostTimeout" => NyxReturnValue::HostTimeout,
+        _ => return None,
+    })
+}
+
+impl CorpusMetadata {
+
+    pub fn new(worker_id: usize, result: &ExecResult) -> CorpusMetadata {
+        CorpusMetadata {
+            worker_id,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |x| x.as_secs()),
+            value: result.value,
+            runtime: result.runtime,
+            dirty_pages: result.dirty_pages,
+            pt_trace_size: result.pt_trace_size,
+            bb_coverage: result.bb_coverage,
+            message: result.message.clone(),
+        }
+    }
+
+    /* "key: value" lines; the (raw) message follows a single "message:" line */
+    pub fn serialize(&self) -> String {
+        let mut data = format!("worker_id: {}\ntimestamp: {}\nvalue: {:?}\nruntime_usec: {}\ndirty_pages: {}\npt_trace_size: {}\nbb_coverage: {}\n",
+            self.worker_id, self.timestamp, self.value, self.runtime.as_micros(), self.dirty_pages, self.pt_trace_size, self.bb_coverage);
+        if let Some(message) = &self.message {
+            data.push_str("message:\n");
+            data.push_str(message);
+        }
+        data
+    }
+
+    pub fn parse(data: &str) -> Option<CorpusMetadata> {
+        let (header, message) = match data.split_once("message:\n") {
+            Some((header, message)) => (header, Some(message.to_string())),
+            None => (data, None),
+        };
+
+        let mut fields = std::collections::HashMap::new();
+        for line in header.lines() {
+            let (key, value) = line.split_once(": ")?;
+            fields.insert(key, value);
+        }
+        let field = |key: &str| fields.get(key).and_then(|x| x.parse::<u64>().ok());
+
+        Some(CorpusMetadata {
+            worker_id: field("worker_id")? as usize,
+            timestamp: field("timestamp")?,
+            value: parse_return_value(fields.get("value")?)?,
+            runtime: Duration::from_micros(field("runtime_usec")?),
+            dirty_pages: field("dirty_pages")? as u32,
+            pt_trace_size: field("pt_trace_size")? as u32,
+            bb_coverage: field("bb_coverage")? as u32,
+            message,
+        })
+    }
+}
+
+/* Content-addressed corpus in <workdir>/corpus/<kind>/<sha1>.bin (+ <sha1>.meta).
+ * Entries are published with link(2), which fails if the entry already exists. Hence, multiple
+ * workers (Parent and Child processes) can store inputs concurrently and only the first one
+ * which stores a specific input is told that it is new.
+ */
+#[derive(Debug, Clone)]
+pub struct Corpus {
+    workdir: String,
+    worker_id: usize,
+}
+
+impl Corpus {
+
+    /* Opens the corpus of a workdir (missing corpus directories are created). */
+    pub fn open(workdir: &str, worker_id: usize) -> io::Result<Corpus> {
+        let corpus = Corpus { workdir: workdir.to_string(), worker_id };
+        for kind in NyxCorpusKind::ALL {
+            fs::create_dir_all(corpus.path(kind))?;
+        }
+        Ok(corpus)
+    }
+
+    /* Opens the corpus of the workdir of the given config (worker id is set by NyxProcess::new()). */
+    pub fn for_config(config: &NyxConfig) -> io::Result<Corpus> {
+        Corpus::open(config.workdir_path(), config.worker_id())
+    }
+
+    pub fn worker_id(&self) -> usize {
+        self.worker_id
+    }
+
+    pub fn path(&self, kind: NyxCorpusKind) -> String {
+        format!("{}/corpus/{}", self.workdir, kind.dir_name())
+    }
+
+    pub fn input_path(&self, kind: NyxCorpusKind, hash: &str) -> String {
+        format!("{}/{}.{}", self.path(kind), hash, INPUT_EXTENSION)
+    }
+
+    pub fn metadata_path(&self, kind: NyxCorpusKind, hash: &str) -> String {
+        format!("{}/{}.{}", self.path(kind), hash, METADATA_EXTENSION)
+    }
+
+    /* hex encoded SHA-1 of an input (name of its corpus entry) */
+    pub fn hash(input: &[u8]) -> String {
+        sha1_smol::Sha1::from(input).digest().to_string()
+    }
+
+    fn write_tmp_file(&self, kind: NyxCorpusKind, data: &[u8]) -> io::Result<String> {
+        let path = format!("{}/.tmp_{}_{}", self.path(kind), std::process::id(), TMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed));
+        fs::write(&path, data)?;
+        Ok(path)
+    }
+
+    /* Writes a file atomically (returns false if it already exists). */
+    fn publish(&self, kind: NyxCorpusKind, data: &[u8], path: &str) -> io::Result<bool> {
+        let tmp_path = self.write_tmp_file(kind, data)?;
+        let result = fs::hard_link(&tmp_path, path);
+        fs::remove_file(&tmp_path)?;
+        match result {
+            Ok(()) => Ok(true),
+            Err(x) if x.kind() == io::ErrorKind::AlreadyExists => Ok(false),
+            Err(x) => Err(x),
+        }
+    }
+
+    /* Stores an input and its metadata. Returns the hash of the input if it has not been stored
+     * before (or None if the entry already exists).
+     */
+    pub fn store(&self, kind: NyxCorpusKind, input: &[u8], metadata: &CorpusMetadata) -> io::Result<Option<String>> {
+        let hash = Corpus::hash(input);
+        let input_path = self.input_path(kind, &hash);
+        if fs::metadata(&input_path).is_ok() {
+            return Ok(None);
+        }
+
+        /* the sidecar is published first, so that every visible input has its metadata (the sidecar
+         * of the first worker is kept, also if it has been left behind without input)
+         */
+        self.publish(kind, metadata.serialize().as_bytes(), &self.metadata_path(kind, &hash))?;
+        match self.publish(kind, input, &input_path)? {
+            true => Ok(Some(hash)),
+            false => Ok(None),
+        }
+    }
+
+    /* Stores an input in the corpus of its execution outcome (inputs resulting in errors are not stored). */
+    pub fn store_result(&self, input: &[u8], result: &ExecResult) -> io::Result<Option<String>> {
+        match NyxCorpusKind::from_return_value(result.value) {
+            Some(kind) => self.store(kind, input, &CorpusMetadata::new(self.worker_id, result)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn contains(&self, kind: NyxCorpusKind, hash: &str) -> bool {
+        fs::metadata(self.input_path(kind, hash)).is_ok()
+    }
+
+    /* Returns the hashes of all entries of a corpus (sorted). */
+    pub fn entries(&self, kind: NyxCorpusKind) -> io::Result<Vec<String>> {
+        let mut entries = vec![];
+        for entry in fs::read_dir(self.path(kind))? {
+            let name = entry?.file_name();
+            if let Some(hash) = name.to_str().and_then(|x| x.strip_suffix(&format!(".{}", INPUT_EXTENSION))) {
+                entries.push(hash.to_string());
+            }
+        }
+        entries.sort();
+        Ok(entries)
+    }
+
+    pub fn load(&self, kind: NyxCorpusKind, hash: &str) -> io::Result<Vec<u8>> {
+        fs::read(self.input_path(kind, hash))
+    }
+
+    pub fn metadata(&self, kind: NyxCorpusKind, hash: &str) -> io::Result<CorpusMetadata> {
+        let data = fs::read_to_string(self.metadata_path(kind, hash))?;
+        CorpusMetadata::parse(&data).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("{}: invalid corpus metadata", hash)))
+    }
+}
+
+impl NyxProcess {
+
+    /* Returns the input which has been set via set_input() (the size is stored in front of the payload). */
+    pub fn current_input(&self) -> &[u8] {
+        let buffer = self.input_buffer();
+        let size = u32::from_ne_bytes(buffer[..4].try_into().unwrap()) as usize;
+        &buffer[4..][..std::cmp::min(size, buffer.len() - 4)]
+    }
+
+    /* Stores the current input in the corpus of its execution outcome. */
+    pub fn store_result(&self, corpus: &Corpus, result: &ExecResult) -> io::Result<Option<String>> {
+        corpus.store_result(self.current_input(), result)
+    }
+}
@@ -0,0 +1,167 @@
+/*
+    libnyx coverage export (drcov / lcov)
+
+    Copyright (C) 2021 Sergej Schumilo
+    This file is part of libnyx.
+
+    libnyx is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 2 of the License, or
+    (at your option) any later version.
+    libnyx is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+    You should have received a copy of the GNU General Public License
+    along with libnyx.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use super::*;
+use crate::symbolizer::Symbolizer;
+
+/* Addresses executed by a set of inputs (collected in trace mode). */
+#[derive(Debug, Default, Clone)]
+pub struct CoverageReport {
+    /* number of inputs which have executed a given address (source or target of an edge) */
+    pub addresses: BTreeMap<u64, u32>,
+    pub inputs: usize,
+}
+
+impl CoverageReport {
+
+    pub fn new() -> CoverageReport {
+        CoverageReport::default()
+    }
+
+    /* Adds the edges of a single input. */
+    pub fn add_trace(&mut self, edges: &[(u64, u64)]) {
+        let addresses: BTreeSet<u64> = edges.iter().flat_map(|(from, to)| [*from, *to]).collect();
+        for addr in addresses.into_iter() {
+            *self.addresses.entry(addr).or_insert(0) += 1;
+        }
+        self.inputs += 1;
+    }
+
+    /* Writes a drcov (version 2) file (e.g. for Lighthouse or bncov). Blocks outside of
+     * all known modules are dropped; block sizes are unknown and reported as 1.
+     */
+    pub fn write_drcov<W: Write>(&self, symbolizer: &Symbolizer, out: W) -> io::Result<()> {
+        let mut out = BufWriter::new(out);
+
+        let blocks: Vec<(u32, u16)> = self.addresses.keys()
+            .filter_map(|addr| {
+                let id = symbolizer.module_index(*addr)?;
+                Some(((*addr - symbolizer.modules[id].image_start) as u32, id as u16))
+            })
+            .collect();
+
+        writeln!(out, "DRCOV VERSION: 2")?;
+        writeln!(out, "DRCOV FLAVOR: libnyx")?;
+        writeln!(out, "Module Table: version 2, count {}", symbolizer.modules.len())?;
+        writeln!(out, "Columns: id, base, end, entry, checksum, timestamp, path")?;
+        for (id, module) in symbolizer.modules.iter().enumerate() {
+            writeln!(out, "{:3}, {:#018x}, {:#018x}, {:#018x}, {:#010x}, {:#010x}, {}", id, module.image_start, module.end, 0, 0, 0, module.path)?;
+        }
+        writeln!(out, "BB Table: {} bbs", blocks.len())?;
+        for (offset, id) in blocks.iter() {
+            /* struct { u32 start; u16 size; u16 mod_id; } */
+            out.write_all(&offset.to_le_bytes())?;
+            out.write_all(&1_u16.to_le_bytes())?;
+            out.write_all(&id.to_le_bytes())?;
+        }
+        out.flush()
+    }
+
+    /* Writes an lcov tracefile (e.g. for genhtml). The hit count of a line is the number of
+     * inputs which have executed it; lines of the modules which have not been executed are
+     * reported with 0 hits. Addresses without DWARF line info are dropped.
+     */
+    pub fn write_lcov<W: Write>(&self, symbolizer: &Symbolizer, out: W) -> io::Result<()> {
+        let mut out = BufWriter::new(out);
+
+        let mut files: BTreeMap<String, BTreeMap<u32, u32>> = BTreeMap::new();
+        for location in symbolizer.modules.iter().flat_map(|x| x.source_lines()) {
+            files.entry(location.file).or_default().entry(location.line).or_insert(0);
+        }
+        for (addr, hits) in self.addresses.iter() {
+            if let Some(location) = symbolizer.source_location(*addr) {
+                let line = files.entry(location.file).or_default().entry(location.line).or_insert(0);
+                *line = (*line).max(*hits);
+            }
+        }
+
+        for (file, lines) in files.iter() {
+            writeln!(out, "TN:")?;
+            writeln!(out, "SF:{}", file)?;
+            for (line, hits) in lines.iter() {
+                writeln!(out, "DA:{},{}", line, hits)?;
+            }
+            writeln!(out, "LF:{}", lines.len())?;
+            writeln!(out, "LH:{}", lines.values().filter(|x| **x > 0).count())?;
+            writeln!(out, "end_of_record")?;
+        }
+        out.flush()
+    }
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), files)?;
+        }
+        else {
+            files.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+impl NyxProcess {
+
+    /* Runs every input of a corpus directory (including sub directories) in trace mode. */
+    pub fn corpus_coverage(&mut self, corpus_dir: &str) -> Result<CoverageReport, NyxError> {
+        let mut inputs = vec![];
+        collect_files(Path::new(corpus_dir), &mut inputs)?;
+        inputs.sort();
+
+        let mut report = CoverageReport::new();
+        for input in inputs.iter() {
+            let data = fs::read(input)?;
+            report.add_trace(&self.trace(&data)?);
+        }
+        Ok(report)
+    }
+
+    /* Collects the coverage of a whole corpus and writes it as drcov and / or lcov file.
+     * Addresses are resolved against the binaries in the sharedir (see Symbolizer::from_sharedir()).
+     */
+    pub fn export_corpus_coverage(&mut self, config: &NyxConfig, corpus_dir: &str, drcov_path: Option<&str>, lcov_path: Option<&str>) -> Result<CoverageReport, NyxError> {
+        self.export_corpus_coverage_with(&Symbolizer::from_sharedir(config), corpus_dir, drcov_path, lcov_path)
+    }
+
+    /* Same as export_corpus_coverage() but resolves addresses against the modules of the given
+     * symbolizer (e.g. position independent binaries added with their load address). Fails if the
+     * symbolizer has no modules at all.
+     */
+    pub fn export_corpus_coverage_with(&mut self, symbolizer: &Symbolizer, corpus_dir: &str, drcov_path: Option<&str>, lcov_path: Option<&str>) -> Result<CoverageReport, NyxError> {
+        if symbolizer.modules.is_empty() {
+            return Err(NyxError::InvalidConfig("no binaries to resolve coverage against (position independent binaries have to be added with their load address)".to_string()));
+        }
+        let report = self.corpus_coverage(corpus_dir)?;
+
+        if let Some(path) = drcov_path {
+            report.write_drcov(symbolizer, File::create(path)?)?;
+        }
+        if let Some(path) = lcov_path {
+            report.write_lcov(symbolizer, File::create(path)?)?;
+        }
+        Ok(report)
+    }
+}
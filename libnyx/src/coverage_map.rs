@@ -0,0 +1,172 @@
+/*
+    libnyx coverage map utilities (AFL-style hit count classes and virgin map)
+
+    Copyright (C) 2021 Sergej Schumilo
+    This file is part of libnyx.
+
+    libnyx is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 2 of the License, or
+    (at your option) any later version.
+    libnyx is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+    You should have received a copy of the GNU General Public License
+    along with libnyx.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::*;
+
+/* The map is processed in 8 byte words; words without any set byte are skipped (the common case). */
+const WORD_SIZE: usize = 8;
+
+/* AFL hit count classes: 1, 2, 3, 4-7, 8-15, 16-31, 32-127, 128+ */
+const COUNT_CLASS_LOOKUP: [u8; 256] = {
+    let mut table = [0_u8; 256];
+    let mut i = 1;
+    while i < 256 {
+        table[i] = match i {
+            1 => 1,
+            2 => 2,
+            3 => 4,
+            4..=7 => 8,
+            8..=15 => 16,
+            16..=31 => 32,
+            32..=127 => 64,
+            _ => 128,
+        };
+        i += 1;
+    }
+    table
+};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum NyxNewBits {
+    None,       // nothing new
+    NewCount,   // a known edge has been hit with a new hit count class
+    NewEdge,    // at least one edge has been hit for the first time
+}
+
+fn word(data: &[u8]) -> u64 {
+    u64::from_ne_bytes(data.try_into().unwrap())
+}
+
+fn new_bits(cur: u8, virgin: u8) -> NyxNewBits {
+    match (cur & virgin, virgin) {
+        (0, _) => NyxNewBits::None,
+        (_, 0xff) => NyxNewBits::NewEdge,
+        _ => NyxNewBits::NewCount,
+    }
+}
+
+/* Replaces all hit counts with their AFL hit count class (in place). */
+pub fn classify_counts(map: &mut [u8]) {
+    let mut chunks = map.chunks_exact_mut(WORD_SIZE);
+    for chunk in &mut chunks {
+        if word(chunk) != 0 {
+            chunk.iter_mut().for_each(|x| *x = COUNT_CLASS_LOOKUP[*x as usize]);
+        }
+    }
+    chunks.into_remainder().iter_mut().for_each(|x| *x = COUNT_CLASS_LOOKUP[*x as usize]);
+}
+
+/* Stable hash of a (classified) map (FNV-1a 64; identical across runs and hosts). */
+pub fn hash(map: &[u8]) -> u64 {
+    map.iter().fold(FNV_OFFSET_BASIS, |hash, x| (hash ^ *x as u64).wrapping_mul(FNV_PRIME))
+}
+
+/* Iterates over all non-zero entries (index, value) of a map. */
+pub fn iter_set(map: &[u8]) -> impl Iterator<Item = (usize, u8)> + '_ {
+    map.chunks(WORD_SIZE)
+        .enumerate()
+        .filter(|(_, chunk)| chunk.len() != WORD_SIZE || word(chunk) != 0)
+        .flat_map(|(i, chunk)| chunk.iter().enumerate().map(move |(j, x)| (i * WORD_SIZE + j, *x)))
+        .filter(|(_, x)| *x != 0)
+}
+
+/* Number of non-zero entries of a map. */
+pub fn count_set(map: &[u8]) -> usize {
+    iter_set(map).count()
+}
+
+/* Virgin map: all hit count classes which have not been observed yet (same semantics as in AFL). */
+#[derive(Debug, Clone)]
+pub struct CoverageMap {
+    pub virgin: Vec<u8>,
+}
+
+impl CoverageMap {
+
+    pub fn new(size: usize) -> CoverageMap {
+        CoverageMap { virgin: vec![0xff; size] }
+    }
+
+    /* Creates a virgin map for the bitmap size negotiated with the agent of the given process. */
+    pub fn for_process(process: &NyxProcess) -> CoverageMap {
+        CoverageMap::new(process.bitmap_buffer_size())
+    }
+
+    pub fn size(&self) -> usize {
+        self.virgin.len()
+    }
+
+    pub fn reset(&mut self) {
+        self.virgin.fill(0xff);
+    }
+
+    /* Checks a classified trace map for new coverage and marks it as observed.
+     * Only the common prefix is compared if the sizes differ.
+     */
+    pub fn has_new_bits(&mut self, trace: &[u8]) -> NyxNewBits {
+        let mut result = NyxNewBits::None;
+        for (cur, virgin) in trace.chunks(WORD_SIZE).zip(self.virgin.chunks_mut(WORD_SIZE)) {
+            if cur.len() == WORD_SIZE && virgin.len() == WORD_SIZE && word(cur) & word(virgin) == 0 {
+                continue;
+            }
+            for (cur, virgin) in cur.iter().zip(virgin.iter_mut()) {
+                result = std::cmp::max(result, new_bits(*cur, *virgin));
+                *virgin &= !cur;
+            }
+        }
+        result
+    }
+
+    /* Same as has_new_bits() but without updating the virgin map. */
+    pub fn would_have_new_bits(&self, trace: &[u8]) -> NyxNewBits {
+        trace.chunks(WORD_SIZE).zip(self.virgin.chunks(WORD_SIZE))
+            .filter(|(cur, virgin)| cur.len() != WORD_SIZE || virgin.len() != WORD_SIZE || word(cur) & word(virgin) != 0)
+            .flat_map(|(cur, virgin)| cur.iter().zip(virgin.iter()))
+            .map(|(cur, virgin)| new_bits(*cur, *virgin))
+            .max()
+            .unwrap_or(NyxNewBits::None)
+    }
+
+    /* Number of edges which have been observed at least once. */
+    pub fn count_covered(&self) -> usize {
+        self.virgin.iter().filter(|x| **x != 0xff).count()
+    }
+}
+
+impl NyxProcess {
+
+    /* Classifies the hit counts of the last execution in place and returns the classified bitmap.
+     * Hit count classes are not idempotent (3 -> 4 -> 8), hence the bitmap is only classified once
+     * per execution; subsequent calls return the already classified bitmap.
+     */
+    pub fn classify_bitmap(&mut self) -> &[u8] {
+        if !self.bitmap_classified {
+            classify_counts(self.bitmap_buffer_mut());
+            self.bitmap_classified = true;
+        }
+        self.bitmap_buffer()
+    }
+
+    /* Classifies the hit counts of the last execution (see classify_bitmap()) and checks them against a virgin map. */
+    pub fn update_coverage(&mut self, map: &mut CoverageMap) -> NyxNewBits {
+        map.has_new_bits(self.classify_bitmap())
+    }
+}
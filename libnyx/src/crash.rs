@@ -0,0 +1,340 @@
+/*
+    libnyx crash classification (KASAN / ASAN reports, kernel oopses and signals)
+
+    Copyright (C) 2021 Sergej Schumilo
+    This file is part of libnyx.
+
+    libnyx is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 2 of the License, or
+    (at your option) any later version.
+    libnyx is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+    You should have received a copy of the GNU General Public License
+    along with libnyx.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::ffi::CStr;
+
+use super::*;
+use crate::corpus::NyxCorpusKind;
+
+/* number of (top) stack frames which are part of the stack signature */
+pub const MAX_SIGNATURE_FRAMES: usize = 5;
+
+/* frames of the sanitizer runtime / report functions (not part of the stack signature) */
+const IGNORED_FRAME_PREFIXES: [&str; 12] = [
+    "dump_stack", "__dump_stack", "show_stack", "print_report", "print_address_description",
+    "kasan", "__kasan", "check_memory_region", "__asan", "__interceptor", "__sanitizer", "asan_",
+];
+
+/* markers which are followed by the faulting address (first match wins) */
+const FAULT_ADDRESS_MARKERS: [&str; 7] = [
+    " at addr ", " on unknown address ", " on address ", "address: ",
+    "for non-canonical address ", "double-free on ", "fault addr ",
+];
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NyxCrashClass {
+    Unknown,
+
+    KasanUseAfterFree,
+    KasanOutOfBounds,
+    KasanDoubleFree,
+    KasanInvalidFree,
+    KasanOther,
+
+    AsanHeapOverflow,
+    AsanStackOverflow,
+    AsanGlobalOverflow,
+    AsanUseAfterFree,
+    AsanDoubleFree,
+    AsanOther,
+
+    KernelBug,      // kernel BUG at ... / BUG_ON()
+    KernelOops,     // page fault, NULL pointer dereference, general protection fault
+    KernelPanic,
+
+    Segv,
+    Abort,
+    Signal,         // any other fatal signal
+}
+
+impl NyxCrashClass {
+
+    fn c_name(&self) -> &'static CStr {
+        match self {
+            NyxCrashClass::Unknown => c"unknown",
+            NyxCrashClass::KasanUseAfterFree => c"kasan-use-after-free",
+            NyxCrashClass::KasanOutOfBounds => c"kasan-out-of-bounds",
+            NyxCrashClass::KasanDoubleFree => c"kasan-double-free",
+            NyxCrashClass::KasanInvalidFree => c"kasan-invalid-free",
+            NyxCrashClass::KasanOther => c"kasan",
+            NyxCrashClass::AsanHeapOverflow => c"asan-heap-buffer-overflow",
+            NyxCrashClass::AsanStackOverflow => c"asan-stack-buffer-overflow",
+            NyxCrashClass::AsanGlobalOverflow => c"asan-global-buffer-overflow",
+            NyxCrashClass::AsanUseAfterFree => c"asan-heap-use-after-free",
+            NyxCrashClass::AsanDoubleFree => c"asan-double-free",
+            NyxCrashClass::AsanOther => c"asan",
+            NyxCrashClass::KernelBug => c"kernel-bug",
+            NyxCrashClass::KernelOops => c"kernel-oops",
+            NyxCrashClass::KernelPanic => c"kernel-panic",
+            NyxCrashClass::Segv => c"segv",
+            NyxCrashClass::Abort => c"abort",
+            NyxCrashClass::Signal => c"signal",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.c_name().to_str().unwrap()
+    }
+
+    pub fn c_name_ptr(&self) -> *const libc::c_char {
+        self.c_name().as_ptr()
+    }
+
+    /* memory safety violations detected by KASAN or ASAN */
+    pub fn is_sanitizer(&self) -> bool {
+        matches!(self,
+            NyxCrashClass::KasanUseAfterFree | NyxCrashClass::KasanOutOfBounds | NyxCrashClass::KasanDoubleFree |
+            NyxCrashClass::KasanInvalidFree | NyxCrashClass::KasanOther |
+            NyxCrashClass::AsanHeapOverflow | NyxCrashClass::AsanStackOverflow | NyxCrashClass::AsanGlobalOverflow |
+            NyxCrashClass::AsanUseAfterFree | NyxCrashClass::AsanDoubleFree | NyxCrashClass::AsanOther)
+    }
+
+    /* NyxReturnValue reported for a crash of this class */
+    pub fn return_value(&self) -> NyxReturnValue {
+        match self.is_sanitizer() {
+            true => NyxReturnValue::Asan,
+            false => NyxReturnValue::Crash,
+        }
+    }
+
+    /* corpus a crash of this class is stored in (corpus/kasan or corpus/crash) */
+    pub fn corpus_kind(&self) -> NyxCorpusKind {
+        match self.is_sanitizer() {
+            true => NyxCorpusKind::Kasan,
+            false => NyxCorpusKind::Crash,
+        }
+    }
+}
+
+impl fmt::Display for NyxCrashClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/* Result of parsing a crash message (aux misc string) reported by the agent. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrashInfo {
+    pub class: NyxCrashClass,
+    pub fault_address: Option<u64>,
+
+    /* function names of the (first) stack trace, innermost frame first */
+    pub frames: Vec<String>,
+}
+
+fn classify_kasan(bug_type: &str) -> NyxCrashClass {
+    if bug_type.contains("use-after-free") {
+        NyxCrashClass::KasanUseAfterFree
+    } else if bug_type.contains("out-of-bounds") {
+        NyxCrashClass::KasanOutOfBounds
+    } else if bug_type.contains("double-free") {
+        NyxCrashClass::KasanDoubleFree
+    } else if bug_type.contains("invalid-free") {
+        NyxCrashClass::KasanInvalidFree
+    } else {
+        NyxCrashClass::KasanOther
+    }
+}
+
+fn classify_asan(bug_type: &str) -> NyxCrashClass {
+    match bug_type.split_whitespace().next().unwrap_or("") {
+        "heap-buffer-overflow" => NyxCrashClass::AsanHeapOverflow,
+        "stack-buffer-overflow" | "stack-buffer-underflow" => NyxCrashClass::AsanStackOverflow,
+        "global-buffer-overflow" => NyxCrashClass::AsanGlobalOverflow,
+        "heap-use-after-free" => NyxCrashClass::AsanUseAfterFree,
+        "attempting" if bug_type.contains("double-free") => NyxCrashClass::AsanDoubleFree,
+        "SEGV" => NyxCrashClass::Segv,
+        "ABRT" => NyxCrashClass::Abort,
+        _ => NyxCrashClass::AsanOther,
+    }
+}
+
+fn classify(message: &str) -> NyxCrashClass {
+    if let Some((_, bug_type)) = message.split_once("BUG: KASAN: ") {
+        return classify_kasan(bug_type.lines().next().unwrap_or(""));
+    }
+    if let Some((_, bug_type)) = message.split_once("ERROR: AddressSanitizer: ") {
+        return classify_asan(bug_type.lines().next().unwrap_or(""));
+    }
+    if message.contains("kernel BUG at ") {
+        return NyxCrashClass::KernelBug;
+    }
+    if message.contains("BUG: unable to handle ") || message.contains("BUG: kernel NULL pointer dereference") || message.contains("general protection fault") {
+        return NyxCrashClass::KernelOops;
+    }
+    if message.contains("Kernel panic") {
+        return NyxCrashClass::KernelPanic;
+    }
+    if message.contains("SIGSEGV") || message.contains("Segmentation fault") {
+        return NyxCrashClass::Segv;
+    }
+    if message.contains("SIGABRT") || message.contains("Aborted") {
+        return NyxCrashClass::Abort;
+    }
+    if message.contains("SIG") || message.contains("signal") {
+        return NyxCrashClass::Signal;
+    }
+    NyxCrashClass::Unknown
+}
+
+fn parse_hex(value: &str) -> Option<u64> {
+    let value = value.strip_prefix("0x").unwrap_or(value);
+    let end = value.find(|x: char| !x.is_ascii_hexdigit()).unwrap_or(value.len());
+    u64::from_str_radix(&value[..end], 16).ok()
+}
+
+fn parse_fault_address(message: &str) -> Option<u64> {
+    message.lines()
+        .flat_map(|line| FAULT_ADDRESS_MARKERS.iter().filter_map(move |marker| line.split_once(marker)))
+        .find_map(|(_, value)| parse_hex(value))
+}
+
+/* strips compiler generated suffixes (foo.cold, foo.isra.0, ...) */
+fn normalize_function(function: &str) -> Option<String> {
+    let function = function.split('.').next().unwrap_or(function);
+    if function.is_empty() || IGNORED_FRAME_PREFIXES.iter().any(|x| function.starts_with(x)) {
+        return None;
+    }
+    Some(function.to_string())
+}
+
+/* Kernel stack frame, e.g. " foo+0x12/0x40 [module]" or " [<ffffffff81234567>] foo+0x12/0x40".
+ * Returns Some(None) for frames which are ignored (unreliable "?" frames, sanitizer runtime).
+ */
+fn parse_kernel_frame(line: &str) -> Option<Option<String>> {
+    let mut line = line.trim();
+    if line.starts_with("[<") {
+        line = line.split_once("] ")?.1;
+    }
+    if line.starts_with("? ") {
+        return Some(None);
+    }
+    let (function, offset) = line.split_whitespace().next()?.split_once('+')?;
+    if !offset.starts_with("0x") || !offset.contains('/') {
+        return None;
+    }
+    Some(normalize_function(function))
+}
+
+/* ASAN stack frame, e.g. "    #0 0x4f5e3 in foo /src/foo.c:12:3" */
+fn parse_asan_frame(line: &str) -> Option<Option<String>> {
+    let mut tokens = line.split_whitespace();
+    if !tokens.next()?.starts_with('#') {
+        return None;
+    }
+    let _pc = tokens.next()?;
+    match (tokens.next(), tokens.next()) {
+        (Some("in"), Some(function)) => Some(normalize_function(function)),
+        _ => Some(None),
+    }
+}
+
+/* Returns the frames of the first stack trace (the faulting RIP is used as innermost kernel frame). */
+fn parse_frames(message: &str) -> Vec<String> {
+    let mut frames = vec![];
+    let mut kernel_trace = false;
+    let mut trace_started = false;
+
+    for line in message.lines() {
+        let line = line.trim();
+        if let Some((_, rip)) = line.split_once("RIP: ") {
+            /* "RIP: 0010:foo+0x12/0x40" */
+            if let Some(Some(function)) = parse_kernel_frame(rip.rsplit(':').next().unwrap_or(rip)) {
+                frames.insert(0, function);
+            }
+            continue;
+        }
+        if line.starts_with("Call Trace:") {
+            kernel_trace = true;
+            continue;
+        }
+        if matches!(line, "<TASK>" | "</TASK>" | "<IRQ>" | "</IRQ>" | "<EOI>") {
+            continue;
+        }
+
+        let frame = match kernel_trace {
+            true => parse_kernel_frame(line),
+            false => parse_asan_frame(line),
+        };
+        match frame {
+            Some(Some(function)) => {
+                trace_started = true;
+                if frames.last() != Some(&function) {
+                    frames.push(function);
+                }
+            },
+            Some(None) => trace_started = true,
+            /* end of the first stack trace */
+            None if trace_started => break,
+            None => {},
+        }
+    }
+    frames
+}
+
+impl CrashInfo {
+
+    pub fn parse(message: &str) -> CrashInfo {
+        CrashInfo {
+            class: classify(message),
+            fault_address: parse_fault_address(message),
+            frames: parse_frames(message),
+        }
+    }
+
+    /* Stable hash of the crash class and the top MAX_SIGNATURE_FRAMES stack frames. */
+    pub fn stack_signature(&self) -> u64 {
+        let mut data = self.class.name().to_string();
+        for frame in self.frames.iter().take(MAX_SIGNATURE_FRAMES) {
+            data.push('\n');
+            data.push_str(frame);
+        }
+        coverage_map::hash(data.as_bytes())
+    }
+}
+
+/* C representation of CrashInfo (frames are only available via the Rust API). */
+#[repr(C)]
+#[derive(Debug)]
+pub struct NyxCrashInfo {
+    pub class: NyxCrashClass,
+    pub has_fault_address: bool,
+    pub fault_address: u64,
+    pub stack_signature: u64,
+    pub num_frames: u32,
+}
+
+impl From<&CrashInfo> for NyxCrashInfo {
+    fn from(info: &CrashInfo) -> Self {
+        NyxCrashInfo {
+            class: info.class,
+            has_fault_address: info.fault_address.is_some(),
+            fault_address: info.fault_address.unwrap_or(0),
+            stack_signature: info.stack_signature(),
+            num_frames: info.frames.len() as u32,
+        }
+    }
+}
+
+impl NyxProcess {
+
+    /* Parses the crash message of the last execution (see aux_string()). */
+    pub fn crash_info(&self) -> CrashInfo {
+        CrashInfo::parse(&self.aux_string())
+    }
+}
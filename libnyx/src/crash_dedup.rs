@@ -0,0 +1,208 @@
+/*
+    libnyx crash deduplication (crash buckets shared by all workers of a workdir)
+
+    Copyright (C) 2021 Sergej Schumilo
+    This file is part of libnyx.
+
+    libnyx is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 2 of the License, or
+    (at your option) any later version.
+    libnyx is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+    You should have received a copy of the GNU General Public License
+    along with libnyx.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::io::Write;
+
+use super::*;
+use crate::crash::{CrashInfo, NyxCrashClass, MAX_SIGNATURE_FRAMES};
+
+/* stored in the workdir (one file per bucket) */
+pub const CRASH_BUCKETS_DIR: &str = "crash_buckets";
+
+/* lines which start a crash report (used as the title of a bucket) */
+const TITLE_MARKERS: [&str; 7] = [
+    "BUG: ", "ERROR: AddressSanitizer", "kernel BUG at", "general protection fault", "Kernel panic", "WARNING: ", "SIG",
+];
+
+/* Masks addresses and pointers (0x..., bare hex words of at least 8 digits) and the pid of ASAN reports. */
+pub fn mask_addresses(line: &str) -> String {
+    let mut line = line;
+    if let Some(rest) = line.strip_prefix("==") {
+        if let Some((pid, rest)) = rest.split_once("==") {
+            if pid.bytes().all(|x| x.is_ascii_digit()) {
+                line = rest;
+            }
+        }
+    }
+
+    let mut result = String::with_capacity(line.len());
+    let mut token = String::new();
+    let flush = |token: &mut String, result: &mut String| {
+        let is_hex = |x: &str| !x.is_empty() && x.bytes().all(|x| x.is_ascii_hexdigit());
+        if token.strip_prefix("0x").is_some_and(is_hex) {
+            result.push_str("0x?");
+        } else if token.len() >= 8 && is_hex(token) && token.bytes().any(|x| x.is_ascii_digit()) {
+            result.push('?');
+        } else {
+            result.push_str(token);
+        }
+        token.clear();
+    };
+
+    for c in line.chars() {
+        if c.is_ascii_alphanumeric() {
+            token.push(c);
+        } else {
+            flush(&mut token, &mut result);
+            result.push(c);
+        }
+    }
+    flush(&mut token, &mut result);
+    result
+}
+
+/* Returns the (masked) title of a crash message: the first line which starts a crash report
+ * (or the first non-empty line if no report is found).
+ */
+pub fn normalize_message(message: &str) -> String {
+    let is_separator = |x: &str| x.bytes().all(|x| x == b'=' || x == b'-');
+    let lines = || message.lines().map(|x| x.trim()).filter(|x| !is_separator(x));
+
+    let title = lines()
+        .find(|line| TITLE_MARKERS.iter().any(|x| line.contains(x)))
+        .or_else(|| lines().next())
+        .unwrap_or("");
+    mask_addresses(title)
+}
+
+/* Crash bucket: crashes with the same key are considered to be duplicates. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrashBucket {
+    pub class: NyxCrashClass,
+
+    /* normalized crash message (see normalize_message()) */
+    pub title: String,
+
+    /* top stack frames (at most MAX_SIGNATURE_FRAMES) */
+    pub frames: Vec<String>,
+
+    /* hash of the classified coverage bitmap (0 to bucket crashes independent of their path) */
+    pub path_hash: u64,
+}
+
+impl CrashBucket {
+
+    pub fn new(message: &str, path_hash: u64) -> CrashBucket {
+        let info = CrashInfo::parse(message);
+        CrashBucket {
+            class: info.class,
+            title: normalize_message(message),
+            frames: info.frames.into_iter().take(MAX_SIGNATURE_FRAMES).collect(),
+            path_hash,
+        }
+    }
+
+    fn describe(&self) -> String {
+        let mut data = format!("class: {}\ntitle: {}\npath_hash: {:016x}\n", self.class, self.title, self.path_hash);
+        for frame in self.frames.iter() {
+            data.push_str(&format!("frame: {}\n", frame));
+        }
+        data
+    }
+
+    /* Stable bucket key (FNV-1a 64 over class, title, frames and path hash). */
+    pub fn key(&self) -> u64 {
+        coverage_map::hash(self.describe().as_bytes())
+    }
+}
+
+/* Set of known crash buckets in <workdir>/crash_buckets/<key>. A bucket is created with O_EXCL,
+ * so only the first worker (Parent or Child process) which observes a crash is told that it is new.
+ */
+#[derive(Debug, Clone)]
+pub struct CrashDedup {
+    path: String,
+
+    /* buckets which are already known to exist (avoids file system lookups) */
+    known: HashSet<u64>,
+}
+
+impl CrashDedup {
+
+    pub fn open(workdir: &str) -> io::Result<CrashDedup> {
+        let path = format!("{}/{}", workdir, CRASH_BUCKETS_DIR);
+        fs::create_dir_all(&path)?;
+        Ok(CrashDedup { path, known: HashSet::new() })
+    }
+
+    pub fn for_config(config: &NyxConfig) -> io::Result<CrashDedup> {
+        CrashDedup::open(config.workdir_path())
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    fn bucket_path(&self, key: u64) -> String {
+        format!("{}/{:016x}", self.path, key)
+    }
+
+    /* Returns true if no worker has observed a crash of this bucket so far (the bucket is persisted). */
+    pub fn is_new_crash(&mut self, bucket: &CrashBucket) -> io::Result<bool> {
+        let key = bucket.key();
+        if self.known.contains(&key) {
+            return Ok(false);
+        }
+
+        let result = match OpenOptions::new().write(true).create_new(true).open(self.bucket_path(key)) {
+            Ok(mut file) => file.write_all(bucket.describe().as_bytes()).map(|_| true),
+            Err(x) if x.kind() == io::ErrorKind::AlreadyExists => Ok(false),
+            Err(x) => Err(x),
+        };
+        if result.is_ok() {
+            self.known.insert(key);
+        }
+        result
+    }
+
+    pub fn contains(&self, key: u64) -> bool {
+        self.known.contains(&key) || fs::metadata(self.bucket_path(key)).is_ok()
+    }
+
+    /* Returns the keys of all buckets of the workdir (sorted). */
+    pub fn buckets(&self) -> io::Result<Vec<u64>> {
+        let mut buckets = vec![];
+        for entry in fs::read_dir(&self.path)? {
+            if let Some(key) = entry?.file_name().to_str().and_then(|x| u64::from_str_radix(x, 16).ok()) {
+                buckets.push(key);
+            }
+        }
+        buckets.sort();
+        Ok(buckets)
+    }
+}
+
+impl NyxProcess {
+
+    /* Hash of the classified coverage bitmap of the last execution (see classify_bitmap()). */
+    pub fn path_hash(&mut self) -> u64 {
+        coverage_map::hash(self.classify_bitmap())
+    }
+
+    /* Returns the crash bucket of the last execution (crash message and coverage path). */
+    pub fn crash_bucket(&mut self) -> CrashBucket {
+        CrashBucket::new(&self.aux_string(), self.path_hash())
+    }
+
+    /* Returns true if the crash of the last execution has not been observed by any worker so far. */
+    pub fn is_new_unique_crash(&mut self, dedup: &mut CrashDedup) -> io::Result<bool> {
+        dedup.is_new_crash(&self.crash_bucket())
+    }
+}
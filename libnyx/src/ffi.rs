@@ -32,6 +32,14 @@ fn __load_c_string_ptr(pointer: *const c_char) -> String {
     c_str.to_str().unwrap().to_string()
 }
 
+/* Same as __load_c_string_ptr() but maps NULL to None. */
+fn __load_optional_c_string_ptr(pointer: *const c_char) -> Option<String> {
+    match pointer.is_null() {
+        true => None,
+        false => Some(__load_c_string_ptr(pointer)),
+    }
+}
+
 /* Helper function to check if the config pointer is valid.
  * Turns the pointer into a reference to a config object.
  */
@@ -162,6 +170,66 @@ pub extern "C" fn nyx_config_set_aux_buffer_size(config: * mut c_void, aux_buffe
     }
 }
 
+/* FFI function to set the deadline (in milliseconds) for QEMU-Nyx to come up after being spawned */
+#[no_mangle]
+pub extern "C" fn nyx_config_set_startup_timeout(config: * mut c_void, timeout_msec: u32) {
+    let cfg = __nyx_config_check_ptr(config);
+
+    assert!(timeout_msec > 0);
+    unsafe{
+        NyxConfig::set_startup_timeout(&mut *cfg, std::time::Duration::from_millis(timeout_msec as u64));
+    }
+}
+
+/* FFI function to enable automatic respawning of QEMU-Nyx after a failed execution (max_retries == 0 disables it) */
+#[no_mangle]
+pub extern "C" fn nyx_config_set_respawn_policy(config: * mut c_void, max_retries: u32, backoff_msec: u32) {
+    let cfg = __nyx_config_check_ptr(config);
+
+    let policy = match max_retries {
+        0 => None,
+        _ => Some(RespawnPolicy { max_retries, backoff: std::time::Duration::from_millis(backoff_msec as u64) }),
+    };
+    unsafe{
+        NyxConfig::set_respawn_policy(&mut *cfg, policy);
+    }
+}
+
+/* FFI function to set the backing of the shm buffers (base_dir may be NULL to keep the default mount). */
+#[no_mangle]
+pub extern "C" fn nyx_config_set_shm_backing(config: * mut c_void, backing: NyxShmBacking, base_dir: *const c_char) {
+    let cfg = __nyx_config_check_ptr(config);
+
+    let base_dir = match base_dir.is_null() {
+        true => None,
+        false => Some(__load_c_string_ptr(base_dir)),
+    };
+    unsafe{
+        NyxConfig::set_shm_backing(&mut *cfg, backing, base_dir);
+    }
+}
+
+/* FFI function to set the namespace of the shm work dirs (returns false if the namespace is invalid). */
+#[no_mangle]
+pub extern "C" fn nyx_config_set_shm_namespace(config: * mut c_void, namespace: *const c_char) -> bool {
+    let namespace = __load_c_string_ptr(namespace);
+    let cfg = __nyx_config_check_ptr(config);
+
+    unsafe{
+        NyxConfig::set_shm_namespace(&mut *cfg, &namespace)
+    }
+}
+
+/* FFI function to set the host-side watchdog as a multiple of the configured timeout (default value is 0, i.e. disabled) */
+#[no_mangle]
+pub extern "C" fn nyx_config_set_host_timeout_factor(config: * mut c_void, factor: u32) {
+    let cfg = __nyx_config_check_ptr(config);
+
+    unsafe{
+        NyxConfig::set_host_timeout_factor(&mut *cfg, factor);
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn nyx_new(config: * mut c_void, worker_id: u32) -> * mut NyxProcess {
     
@@ -213,6 +281,69 @@ pub extern "C" fn nyx_get_bitmap_buffer_size(nyx_process: * mut NyxProcess) -> u
     }
 }
 
+#[no_mangle]
+pub extern "C" fn nyx_get_ijon_buffer(nyx_process: * mut NyxProcess) -> *const u8 {
+    unsafe{
+        (*__nyx_process_check_ptr(nyx_process)).ijon_buffer().as_ptr()
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn nyx_get_ijon_buffer_size(nyx_process: * mut NyxProcess) -> usize {
+    unsafe{
+        (*__nyx_process_check_ptr(nyx_process)).ijon_buffer().len()
+    }
+}
+
+/* FFI function to enable (default) or disable clearing the ijon buffer before every execution */
+#[no_mangle]
+pub extern "C" fn nyx_set_ijon_reset(nyx_process: * mut NyxProcess, enable: bool) {
+    unsafe{
+        (*__nyx_process_check_ptr(nyx_process)).set_ijon_reset(enable);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn nyx_ijon_executed_opcode_num(nyx_process: * mut NyxProcess) -> u32 {
+    unsafe{
+        (*__nyx_process_check_ptr(nyx_process)).ijon_executed_opcode_num()
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn nyx_ijon_max_slots(nyx_process: * mut NyxProcess) -> u32 {
+    unsafe{
+        (*__nyx_process_check_ptr(nyx_process)).ijon_max_slots() as u32
+    }
+}
+
+/* Returns the value of an ijon max slot of the last execution (0 if the slot does not exist). */
+#[no_mangle]
+pub extern "C" fn nyx_ijon_max(nyx_process: * mut NyxProcess, slot: u32) -> u64 {
+    unsafe{
+        (*__nyx_process_check_ptr(nyx_process)).ijon_max(slot as usize).unwrap_or(0)
+    }
+}
+
+/* FFI function to query the agent capabilities (returns false if the agent reports unknown values). */
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn nyx_get_capabilities(nyx_process: * mut NyxProcess, capabilities: *mut NyxCapabilities) -> bool {
+    unsafe{
+        assert!(!capabilities.is_null());
+        match (*__nyx_process_check_ptr(nyx_process)).capabilities() {
+            Ok(x) => {
+                *capabilities = x;
+                true
+            },
+            Err(x) => {
+                println!("[!] libnyx: {}", x);
+                false
+            },
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn nyx_get_target_hash(config: * mut c_void, buffer: *mut u8) -> bool {
     let cfg = __nyx_config_check_ptr(config);
@@ -271,11 +402,425 @@ pub extern "C" fn nyx_option_apply(nyx_process: * mut NyxProcess) {
     }
 }
 
+/* Restarts the QEMU-Nyx process (returns false on failure). Pointers returned by nyx_get_aux_buffer(),
+ * nyx_get_input_buffer() and nyx_get_bitmap_buffer() remain valid.
+ */
+#[no_mangle]
+pub extern "C" fn nyx_restart(nyx_process: * mut NyxProcess) -> bool {
+    unsafe{
+        match (*__nyx_process_check_ptr(nyx_process)).restart() {
+            Ok(()) => true,
+            Err(x) => {
+                println!("[!] libnyx failed to restart QEMU-Nyx: {}", x);
+                false
+            },
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn nyx_exec(nyx_process: * mut NyxProcess) -> NyxReturnValue {
     
     unsafe{
-        (*__nyx_process_check_ptr(nyx_process)).exec()
+        match (*__nyx_process_check_ptr(nyx_process)).exec() {
+            Ok(x) => x,
+            Err(x) => NyxReturnValue::from(&x),
+        }
+    }
+}
+
+/* Like nyx_exec(), but also returns the statistics of this execution (all zero if the execution has failed). */
+#[no_mangle]
+pub extern "C" fn nyx_exec_result(nyx_process: * mut NyxProcess) -> NyxExecResult {
+
+    unsafe{
+        match (*__nyx_process_check_ptr(nyx_process)).exec_result() {
+            Ok(x) => NyxExecResult::from(&x),
+            Err(x) => NyxExecResult {
+                value: NyxReturnValue::from(&x),
+                message_len: 0,
+                runtime_sec: 0,
+                runtime_usec: 0,
+                dirty_pages: 0,
+                pt_trace_size: 0,
+                bb_coverage: 0,
+                pt_overflow: false,
+                reloaded: false,
+                tmp_snapshot_created: false,
+            },
+        }
+    }
+}
+
+/* FFI function to run the current input in redqueen mode. Up to max_cmps compare records are copied to cmps.
+ * Returns the total number of records (may exceed max_cmps) or -1 if the execution has failed.
+ */
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn nyx_exec_redqueen(nyx_process: * mut NyxProcess, cmps: *mut redqueen::NyxRedqueenCmp, max_cmps: u32) -> i32 {
+
+    unsafe{
+        match (*__nyx_process_check_ptr(nyx_process)).exec_redqueen() {
+            Ok(x) => {
+                for (i, cmp) in x.iter().take(max_cmps as usize).enumerate() {
+                    assert!(!cmps.is_null());
+                    *cmps.add(i) = redqueen::NyxRedqueenCmp::from(cmp);
+                }
+                x.len() as i32
+            },
+            Err(x) => {
+                println!("[!] libnyx: redqueen execution failed: {}", x);
+                -1
+            },
+        }
+    }
+}
+
+/* FFI function to create input-to-state candidates for the given input and redqueen records.
+ * Candidates have the same size as the input and are written back to back to out (up to max_candidates).
+ * If verify is set, every candidate is run in redqueen mode first and only those which flip their
+ * targeted comparison are returned. Returns the number of candidates written or -1 on error.
+ */
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn nyx_i2s_candidates(nyx_process: * mut NyxProcess, input: *const u8, input_len: u32, cmps: *const redqueen::NyxRedqueenCmp, num_cmps: u32, verify: bool, out: *mut u8, max_candidates: u32) -> i32 {
+
+    unsafe{
+        assert!(!input.is_null() && !out.is_null() && (!cmps.is_null() || num_cmps == 0));
+        let input = std::slice::from_raw_parts(input, input_len as usize);
+        let cmps: Vec<RedqueenCmp> = (0..num_cmps as usize).map(|i| RedqueenCmp::from(&*cmps.add(i))).collect();
+
+        let mut written = 0;
+        for candidate in i2s::i2s_candidates(input, &cmps).iter() {
+            if written == max_candidates as usize {
+                break;
+            }
+            if verify {
+                match (*__nyx_process_check_ptr(nyx_process)).i2s_verify_candidate(candidate) {
+                    Ok(true) => {},
+                    Ok(false) => continue,
+                    Err(x) => {
+                        println!("[!] libnyx: I2S verification failed: {}", x);
+                        return -1;
+                    },
+                }
+            }
+            std::ptr::copy(candidate.input.as_ptr(), out.add(written * input.len()), input.len());
+            written += 1;
+        }
+        written as i32
+    }
+}
+
+/* FFI function to run an input in trace mode. Up to max_edges unique edges are copied to edges.
+ * Returns the total number of unique edges (may exceed max_edges) or -1 if the execution has failed.
+ */
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn nyx_trace(nyx_process: * mut NyxProcess, input: *const u8, input_len: u32, edges: *mut trace::NyxEdge, max_edges: u32) -> i32 {
+
+    unsafe{
+        assert!(!input.is_null());
+        let input = std::slice::from_raw_parts(input, input_len as usize);
+
+        match (*__nyx_process_check_ptr(nyx_process)).trace(input) {
+            Ok(x) => {
+                for (i, (from, to)) in x.iter().take(max_edges as usize).enumerate() {
+                    assert!(!edges.is_null());
+                    *edges.add(i) = trace::NyxEdge { from: *from, to: *to };
+                }
+                x.len() as i32
+            },
+            Err(x) => {
+                println!("[!] libnyx: trace execution failed: {}", x);
+                -1
+            },
+        }
+    }
+}
+
+/* FFI function to trace all inputs of a corpus directory and to write the coverage as drcov and / or
+ * lcov file (drcov_path / lcov_path may be NULL). Returns false on error.
+ */
+#[no_mangle]
+pub extern "C" fn nyx_export_coverage(config: * mut c_void, nyx_process: * mut NyxProcess, corpus_dir: *const c_char, drcov_path: *const c_char, lcov_path: *const c_char) -> bool {
+    let cfg = __nyx_config_check_ptr(config);
+    let corpus_dir = __load_c_string_ptr(corpus_dir);
+    let drcov_path = __load_optional_c_string_ptr(drcov_path);
+    let lcov_path = __load_optional_c_string_ptr(lcov_path);
+
+    unsafe{
+        match (*__nyx_process_check_ptr(nyx_process)).export_corpus_coverage(&*cfg, &corpus_dir, drcov_path.as_deref(), lcov_path.as_deref()) {
+            Ok(_) => true,
+            Err(x) => {
+                println!("[!] libnyx: coverage export failed: {}", x);
+                false
+            },
+        }
+    }
+}
+
+/* Helper function to check if the symbolizer pointer is valid. */
+fn __nyx_symbolizer_check_ptr(symbolizer: * mut c_void) -> *mut symbolizer::Symbolizer {
+    let symbolizer = symbolizer as *mut symbolizer::Symbolizer;
+    assert!(!symbolizer.is_null() && symbolizer.is_aligned());
+
+    symbolizer
+}
+
+/* Loads the symbols of all binaries of a target (see Symbolizer::from_sharedir()) and returns
+ * a raw pointer to the symbolizer object (to be released via nyx_symbolizer_free()).
+ */
+#[no_mangle]
+pub extern "C" fn nyx_symbolizer_new(config: * mut c_void) -> *mut c_void {
+    let cfg = __nyx_config_check_ptr(config);
+
+    let symbolizer = unsafe { symbolizer::Symbolizer::from_sharedir(&*cfg) };
+    Box::into_raw(Box::new(symbolizer)) as *mut c_void
+}
+
+/* Adds a (position independent) binary which is loaded at the given base address. */
+#[no_mangle]
+pub extern "C" fn nyx_symbolizer_add_module(symbolizer: * mut c_void, path: *const c_char, base: u64) -> bool {
+    let symbolizer = __nyx_symbolizer_check_ptr(symbolizer);
+    let path = __load_c_string_ptr(path);
+
+    unsafe{
+        match (*symbolizer).add_module(&path, base) {
+            Ok(_) => true,
+            Err(x) => {
+                println!("[!] libnyx: cannot load {}: {}", path, x);
+                false
+            },
+        }
+    }
+}
+
+/* Writes "func+0xoff (file:line)" (or the plain address) as null-terminated string into buffer
+ * and returns the length of the string (truncated to size-1 bytes).
+ */
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn nyx_symbolize(symbolizer: * mut c_void, addr: u64, buffer: *mut u8, size: u32) -> u32 {
+    let symbolizer = __nyx_symbolizer_check_ptr(symbolizer);
+    if buffer.is_null() || size == 0 {
+        return 0;
+    }
+
+    unsafe{
+        let symbol = (*symbolizer).symbolize_str(addr);
+        let len = std::cmp::min(symbol.len(), size as usize - 1);
+        std::ptr::copy(symbol.as_ptr(), buffer, len);
+        *buffer.add(len) = 0;
+        len as u32
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn nyx_symbolizer_free(symbolizer: * mut c_void) {
+    if symbolizer.is_null() { return; }
+    let symbolizer = __nyx_symbolizer_check_ptr(symbolizer);
+
+    unsafe {
+        drop(Box::from_raw(symbolizer));
+    }
+}
+
+/* Same as nyx_export_coverage() but resolves addresses against the modules of the given symbolizer
+ * (e.g. position independent binaries added via nyx_symbolizer_add_module()). Returns false on error.
+ */
+#[no_mangle]
+pub extern "C" fn nyx_export_coverage_with_symbolizer(nyx_process: * mut NyxProcess, symbolizer: * mut c_void, corpus_dir: *const c_char, drcov_path: *const c_char, lcov_path: *const c_char) -> bool {
+    let symbolizer = __nyx_symbolizer_check_ptr(symbolizer);
+    let corpus_dir = __load_c_string_ptr(corpus_dir);
+    let drcov_path = __load_optional_c_string_ptr(drcov_path);
+    let lcov_path = __load_optional_c_string_ptr(lcov_path);
+
+    unsafe{
+        match (*__nyx_process_check_ptr(nyx_process)).export_corpus_coverage_with(&*symbolizer, &corpus_dir, drcov_path.as_deref(), lcov_path.as_deref()) {
+            Ok(_) => true,
+            Err(x) => {
+                println!("[!] libnyx: coverage export failed: {}", x);
+                false
+            },
+        }
+    }
+}
+
+/* Helper function to check if the coverage map pointer is valid. */
+fn __nyx_coverage_map_check_ptr(map: * mut c_void) -> *mut coverage_map::CoverageMap {
+    let map = map as *mut coverage_map::CoverageMap;
+    assert!(!map.is_null() && map.is_aligned());
+
+    map
+}
+
+/* Creates a virgin map of the given size (see nyx_get_bitmap_buffer_size()). */
+#[no_mangle]
+pub extern "C" fn nyx_coverage_map_new(size: usize) -> *mut c_void {
+    Box::into_raw(Box::new(coverage_map::CoverageMap::new(size))) as *mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn nyx_coverage_map_free(map: * mut c_void) {
+    if map.is_null() { return; }
+    let map = __nyx_coverage_map_check_ptr(map);
+
+    unsafe {
+        drop(Box::from_raw(map));
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn nyx_coverage_map_reset(map: * mut c_void) {
+    unsafe{
+        (*__nyx_coverage_map_check_ptr(map)).reset();
+    }
+}
+
+/* Checks a classified trace map for new coverage and marks it as observed. */
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn nyx_coverage_map_has_new_bits(map: * mut c_void, trace: *const u8, size: usize) -> coverage_map::NyxNewBits {
+    assert!(!trace.is_null());
+
+    unsafe{
+        (*__nyx_coverage_map_check_ptr(map)).has_new_bits(std::slice::from_raw_parts(trace, size))
+    }
+}
+
+/* Same as nyx_coverage_map_has_new_bits() but without updating the virgin map. */
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn nyx_coverage_map_would_have_new_bits(map: * mut c_void, trace: *const u8, size: usize) -> coverage_map::NyxNewBits {
+    assert!(!trace.is_null());
+
+    unsafe{
+        (*__nyx_coverage_map_check_ptr(map)).would_have_new_bits(std::slice::from_raw_parts(trace, size))
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn nyx_coverage_map_count_covered(map: * mut c_void) -> usize {
+    unsafe{
+        (*__nyx_coverage_map_check_ptr(map)).count_covered()
+    }
+}
+
+/* Classifies the bitmap of the last execution in place and checks it against the given virgin map. */
+#[no_mangle]
+pub extern "C" fn nyx_update_coverage(nyx_process: * mut NyxProcess, map: * mut c_void) -> coverage_map::NyxNewBits {
+    unsafe{
+        (*__nyx_process_check_ptr(nyx_process)).update_coverage(&mut *__nyx_coverage_map_check_ptr(map))
+    }
+}
+
+/* Helper function to check if the global coverage map pointer is valid. */
+fn __nyx_global_coverage_map_check_ptr(map: * mut c_void) -> *mut global_coverage::GlobalCoverageMap {
+    let map = map as *mut global_coverage::GlobalCoverageMap;
+    assert!(!map.is_null() && map.is_aligned());
+
+    map
+}
+
+/* Opens (or creates) the virgin map shared by all workers of a workdir (returns NULL on error). */
+#[no_mangle]
+pub extern "C" fn nyx_global_coverage_map_open(workdir: *const c_char, size: usize) -> *mut c_void {
+    let workdir = __load_c_string_ptr(workdir);
+
+    match global_coverage::GlobalCoverageMap::open(&workdir, size) {
+        Ok(x) => Box::into_raw(Box::new(x)) as *mut c_void,
+        Err(x) => {
+            println!("[!] libnyx: cannot open global coverage map: {}", x);
+            std::ptr::null_mut()
+        },
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn nyx_global_coverage_map_free(map: * mut c_void) {
+    if map.is_null() { return; }
+    let map = __nyx_global_coverage_map_check_ptr(map);
+
+    unsafe {
+        drop(Box::from_raw(map));
+    }
+}
+
+/* Atomically merges a classified trace map and returns whether it is new globally. */
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn nyx_global_coverage_map_merge(map: * mut c_void, trace: *const u8, size: usize) -> coverage_map::NyxNewBits {
+    assert!(!trace.is_null());
+
+    unsafe{
+        (*__nyx_global_coverage_map_check_ptr(map)).merge(std::slice::from_raw_parts(trace, size))
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn nyx_global_coverage_map_count_covered(map: * mut c_void) -> usize {
+    unsafe{
+        (*__nyx_global_coverage_map_check_ptr(map)).count_covered()
+    }
+}
+
+/* Classifies the bitmap of the last execution in place and merges it into the global map. */
+#[no_mangle]
+pub extern "C" fn nyx_update_global_coverage(nyx_process: * mut NyxProcess, map: * mut c_void) -> coverage_map::NyxNewBits {
+    unsafe{
+        (*__nyx_process_check_ptr(nyx_process)).update_global_coverage(&*__nyx_global_coverage_map_check_ptr(map))
+    }
+}
+
+/* Classifies the bitmap of the last execution in place (only once per execution; nyx_update_coverage()
+ * and nyx_update_global_coverage() do this implicitly). Must be used instead of nyx_classify_counts()
+ * on the bitmap buffer if it is combined with these functions.
+ */
+#[no_mangle]
+pub extern "C" fn nyx_classify_bitmap(nyx_process: * mut NyxProcess) {
+    unsafe{
+        (*__nyx_process_check_ptr(nyx_process)).classify_bitmap();
+    }
+}
+
+/* Replaces all hit counts of a bitmap with their AFL hit count class (in place). */
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn nyx_classify_counts(buffer: *mut u8, size: usize) {
+    assert!(!buffer.is_null());
+
+    unsafe{
+        coverage_map::classify_counts(std::slice::from_raw_parts_mut(buffer, size));
+    }
+}
+
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn nyx_bitmap_hash(buffer: *const u8, size: usize) -> u64 {
+    assert!(!buffer.is_null());
+
+    unsafe{
+        coverage_map::hash(std::slice::from_raw_parts(buffer, size))
+    }
+}
+
+/* Stores the indices of up to max_entries non-zero bitmap entries and returns the total number of non-zero entries. */
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn nyx_bitmap_set_entries(buffer: *const u8, size: usize, indices: *mut u32, max_entries: u32) -> u32 {
+    assert!(!buffer.is_null());
+
+    unsafe{
+        let mut num = 0;
+        for (index, _) in coverage_map::iter_set(std::slice::from_raw_parts(buffer, size)) {
+            if num < max_entries && !indices.is_null() {
+                *indices.add(num as usize) = index as u32;
+            }
+            num += 1;
+        }
+        num
     }
 }
 
@@ -319,6 +864,23 @@ pub extern "C" fn nyx_get_aux_string(nyx_process: * mut NyxProcess, buffer: *mut
     }
 }
 
+/* FFI function to classify the crash message of the last execution (class, faulting address and stack signature). */
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn nyx_get_crash_info(nyx_process: * mut NyxProcess, crash_info: *mut crash::NyxCrashInfo) {
+    assert!(!crash_info.is_null());
+
+    unsafe{
+        *crash_info = crash::NyxCrashInfo::from(&(*__nyx_process_check_ptr(nyx_process)).crash_info());
+    }
+}
+
+/* Returns the name of a crash class as a static null-terminated string (e.g. "kasan-use-after-free"). */
+#[no_mangle]
+pub extern "C" fn nyx_crash_class_name(class: crash::NyxCrashClass) -> *const c_char {
+    class.c_name_ptr()
+}
+
 
 #[no_mangle]
 pub extern "C" fn nyx_set_hprintf_fd(nyx_process: * mut NyxProcess, fd: i32) {
@@ -346,4 +908,206 @@ pub extern "C" fn nyx_remove_work_dir(workdir: *const c_char) -> bool {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+/* Helper function to check if the ijon aggregator pointer is valid. */
+fn __nyx_ijon_aggregator_check_ptr(aggregator: * mut c_void) -> *mut ijon::IjonAggregator {
+    let aggregator = aggregator as *mut ijon::IjonAggregator;
+    assert!(!aggregator.is_null() && aggregator.is_aligned());
+
+    aggregator
+}
+
+/* Creates an aggregator tracking the maximum of every ijon max slot (see nyx_ijon_max_slots()). */
+#[no_mangle]
+pub extern "C" fn nyx_ijon_aggregator_new(slots: u32) -> *mut c_void {
+    Box::into_raw(Box::new(ijon::IjonAggregator::new(slots as usize))) as *mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn nyx_ijon_aggregator_free(aggregator: * mut c_void) {
+    if aggregator.is_null() { return; }
+    let aggregator = __nyx_ijon_aggregator_check_ptr(aggregator);
+
+    unsafe {
+        drop(Box::from_raw(aggregator));
+    }
+}
+
+/* Merges the ijon max values of the last execution into the aggregator. Stores up to max_slots
+ * improved slot indices and returns the total number of improved slots.
+ */
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn nyx_ijon_aggregator_update(aggregator: * mut c_void, nyx_process: * mut NyxProcess, improved: *mut u32, max_slots: u32) -> u32 {
+    unsafe{
+        let slots = (*__nyx_process_check_ptr(nyx_process)).update_ijon(&mut *__nyx_ijon_aggregator_check_ptr(aggregator));
+        if !improved.is_null() {
+            for (i, slot) in slots.iter().take(max_slots as usize).enumerate() {
+                *improved.add(i) = *slot as u32;
+            }
+        }
+        slots.len() as u32
+    }
+}
+
+/* Returns the aggregated maximum of an ijon max slot (0 if the slot does not exist). */
+#[no_mangle]
+pub extern "C" fn nyx_ijon_aggregator_max(aggregator: * mut c_void, slot: u32) -> u64 {
+    unsafe{
+        let aggregator = &*__nyx_ijon_aggregator_check_ptr(aggregator);
+        aggregator.max.get(slot as usize).copied().unwrap_or(0)
+    }
+}
+
+/* Helper function to check if the corpus pointer is valid. */
+fn __nyx_corpus_check_ptr(corpus: * mut c_void) -> *mut corpus::Corpus {
+    let corpus = corpus as *mut corpus::Corpus;
+    assert!(!corpus.is_null() && corpus.is_aligned());
+
+    corpus
+}
+
+/* Opens the corpus (corpus/normal, crash, kasan, timeout) of a workdir (returns NULL on error). */
+#[no_mangle]
+pub extern "C" fn nyx_corpus_open(workdir: *const c_char, worker_id: u32) -> *mut c_void {
+    let workdir = __load_c_string_ptr(workdir);
+
+    match corpus::Corpus::open(&workdir, worker_id as usize) {
+        Ok(x) => Box::into_raw(Box::new(x)) as *mut c_void,
+        Err(x) => {
+            println!("[!] libnyx: cannot open corpus: {}", x);
+            std::ptr::null_mut()
+        },
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn nyx_corpus_free(corpus: * mut c_void) {
+    if corpus.is_null() { return; }
+    let corpus = __nyx_corpus_check_ptr(corpus);
+
+    unsafe {
+        drop(Box::from_raw(corpus));
+    }
+}
+
+/* Stores an input in the given corpus. The hash of the input (41 bytes including the null terminator)
+ * is copied to hash (if not NULL). Returns 1 if the input is new, 0 if it has already been stored or
+ * -1 on error. The message of the metadata is taken from the aux buffer (see nyx_get_aux_string()).
+ */
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn nyx_corpus_store(corpus: * mut c_void, nyx_process: * mut NyxProcess, kind: corpus::NyxCorpusKind, input: *const u8, input_len: u32, result: *const NyxExecResult, hash: *mut c_char) -> i32 {
+    assert!(!input.is_null() && !result.is_null());
+
+    unsafe{
+        let corpus = &*__nyx_corpus_check_ptr(corpus);
+        let process = &*__nyx_process_check_ptr(nyx_process);
+        let result = &*result;
+
+        let input = std::slice::from_raw_parts(input, input_len as usize);
+
+        let result = ExecResult {
+            value: result.value,
+            message: if result.message_len != 0 { Some(process.aux_string()) } else { None },
+            runtime: std::time::Duration::from_secs(result.runtime_sec as u64) + std::time::Duration::from_micros(result.runtime_usec as u64),
+            dirty_pages: result.dirty_pages,
+            pt_trace_size: result.pt_trace_size,
+            bb_coverage: result.bb_coverage,
+            pt_overflow: result.pt_overflow,
+            reloaded: result.reloaded,
+            tmp_snapshot_created: result.tmp_snapshot_created,
+        };
+
+        match corpus.store(kind, input, &corpus::CorpusMetadata::new(corpus.worker_id(), &result)) {
+            Ok(x) => {
+                if !hash.is_null() {
+                    let digest = corpus::Corpus::hash(input);
+                    std::ptr::copy(digest.as_ptr(), hash as *mut u8, digest.len());
+                    *hash.add(digest.len()) = 0;
+                }
+                x.is_some() as i32
+            },
+            Err(x) => {
+                println!("[!] libnyx: cannot store corpus entry: {}", x);
+                -1
+            },
+        }
+    }
+}
+
+/* Returns true if an input with the given (hex encoded) hash is stored in the given corpus. */
+#[no_mangle]
+pub extern "C" fn nyx_corpus_contains(corpus: * mut c_void, kind: corpus::NyxCorpusKind, hash: *const c_char) -> bool {
+    let hash = __load_c_string_ptr(hash);
+
+    unsafe{
+        (*__nyx_corpus_check_ptr(corpus)).contains(kind, &hash)
+    }
+}
+
+/* Helper function to check if the crash dedup pointer is valid. */
+fn __nyx_crash_dedup_check_ptr(dedup: * mut c_void) -> *mut crash_dedup::CrashDedup {
+    let dedup = dedup as *mut crash_dedup::CrashDedup;
+    assert!(!dedup.is_null() && dedup.is_aligned());
+
+    dedup
+}
+
+/* Opens (or creates) the crash buckets shared by all workers of a workdir (returns NULL on error). */
+#[no_mangle]
+pub extern "C" fn nyx_crash_dedup_open(workdir: *const c_char) -> *mut c_void {
+    let workdir = __load_c_string_ptr(workdir);
+
+    match crash_dedup::CrashDedup::open(&workdir) {
+        Ok(x) => Box::into_raw(Box::new(x)) as *mut c_void,
+        Err(x) => {
+            println!("[!] libnyx: cannot open crash buckets: {}", x);
+            std::ptr::null_mut()
+        },
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn nyx_crash_dedup_free(dedup: * mut c_void) {
+    if dedup.is_null() { return; }
+    let dedup = __nyx_crash_dedup_check_ptr(dedup);
+
+    unsafe {
+        drop(Box::from_raw(dedup));
+    }
+}
+
+/* Returns 1 if the crash of the last execution is a new unique crash, 0 if its bucket is already known or -1 on error. */
+#[no_mangle]
+pub extern "C" fn nyx_crash_dedup_is_new(dedup: * mut c_void, nyx_process: * mut NyxProcess) -> i32 {
+    unsafe{
+        match (*__nyx_process_check_ptr(nyx_process)).is_new_unique_crash(&mut *__nyx_crash_dedup_check_ptr(dedup)) {
+            Ok(x) => x as i32,
+            Err(x) => {
+                println!("[!] libnyx: cannot store crash bucket: {}", x);
+                -1
+            },
+        }
+    }
+}
+
+/* Returns the number of crash buckets of the workdir (or -1 on error). */
+#[no_mangle]
+pub extern "C" fn nyx_crash_dedup_count(dedup: * mut c_void) -> i64 {
+    unsafe{
+        match (*__nyx_crash_dedup_check_ptr(dedup)).buckets() {
+            Ok(x) => x.len() as i64,
+            Err(_) => -1,
+        }
+    }
+}
+
+/* Returns the crash bucket key of the last execution. */
+#[no_mangle]
+pub extern "C" fn nyx_crash_bucket_key(nyx_process: * mut NyxProcess) -> u64 {
+    unsafe{
+        (*__nyx_process_check_ptr(nyx_process)).crash_bucket().key()
+    }
+}
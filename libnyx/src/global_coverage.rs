@@ -0,0 +1,155 @@
+/*
+    libnyx global coverage map (virgin map shared by all workers of a campaign)
+
+    Copyright (C) 2021 Sergej Schumilo
+    This file is part of libnyx.
+
+    libnyx is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 2 of the License, or
+    (at your option) any later version.
+    libnyx is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+    You should have received a copy of the GNU General Public License
+    along with libnyx.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::Write;
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::*;
+use crate::coverage_map::NyxNewBits;
+
+/* stored in the workdir (next to the page_cache.* files) */
+pub const GLOBAL_VIRGIN_MAP_FILE: &str = "global_virgin_map";
+
+const WORD_SIZE: usize = std::mem::size_of::<u64>();
+
+/* Virgin map in shared memory. Every worker of a campaign (Parent and Child processes) maps the same
+ * file; a trace is merged with atomic fetch_and operations, so only the first worker which observes
+ * a new hit count class is told that it is new.
+ */
+pub struct GlobalCoverageMap {
+    path: String,
+    size: usize,
+    words: &'static [AtomicU64],
+}
+
+/* flock() wrapper (a mapping of the file keeps the lock alive -> unlock explicitly) */
+fn flock(file: &File, operation: i32) -> io::Result<()> {
+    match unsafe { libc::flock(file.as_raw_fd(), operation) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+impl GlobalCoverageMap {
+
+    /* Opens (or creates) the global virgin map of a workdir. All workers have to use the same map size.
+     * Has to be called after the process has been created (the Parent clears the workdir on startup).
+     */
+    pub fn open(workdir: &str, size: usize) -> io::Result<GlobalCoverageMap> {
+        if size == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "cannot create an empty global coverage map"));
+        }
+        let path = format!("{}/{}", workdir, GLOBAL_VIRGIN_MAP_FILE);
+        let file_size = size.div_ceil(WORD_SIZE) * WORD_SIZE;
+
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+
+        /* the first worker initializes the map (all other workers wait for the lock) */
+        flock(&file, libc::LOCK_EX)?;
+        let result = match file.metadata()?.len() {
+            0 => file.write_all(&vec![0xff; file_size]).and_then(|_| file.sync_all()),
+            x if x == file_size as u64 => Ok(()),
+            x => Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}: map size mismatch ({} vs. {} bytes)", path, x, file_size))),
+        };
+        flock(&file, libc::LOCK_UN)?;
+        result?;
+
+        let words = unsafe {
+            let ptr = libc::mmap(std::ptr::null_mut(), file_size, libc::PROT_READ | libc::PROT_WRITE, libc::MAP_SHARED, file.as_raw_fd(), 0);
+            if ptr == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            std::slice::from_raw_parts(ptr as *const AtomicU64, file_size / WORD_SIZE)
+        };
+
+        Ok(GlobalCoverageMap { path, size, words })
+    }
+
+    /* Opens the global virgin map of the workdir of a process (map size is the negotiated bitmap size). */
+    pub fn for_process(config: &NyxConfig, process: &NyxProcess) -> io::Result<GlobalCoverageMap> {
+        GlobalCoverageMap::open(config.workdir_path(), process.bitmap_buffer_size())
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /* Atomically merges a classified trace map and returns whether it contains coverage which
+     * has not been seen by any worker so far. Only the common prefix is merged if the sizes differ.
+     */
+    pub fn merge(&self, trace: &[u8]) -> NyxNewBits {
+        let size = std::cmp::min(trace.len(), self.size);
+        let mut result = NyxNewBits::None;
+
+        for (cur, virgin) in trace[..size].chunks(WORD_SIZE).zip(self.words.iter()) {
+            let mut bytes = [0_u8; WORD_SIZE];
+            bytes[..cur.len()].copy_from_slice(cur);
+            let cur = u64::from_ne_bytes(bytes);
+
+            if cur == 0 || virgin.load(Ordering::Relaxed) & cur == 0 {
+                continue;
+            }
+            let prev = virgin.fetch_and(!cur, Ordering::Relaxed);
+            let new = (prev & cur).to_ne_bytes();
+
+            for (new, prev) in new.iter().zip(prev.to_ne_bytes().iter()) {
+                result = match (*new, *prev) {
+                    (0, _) => result,
+                    (_, 0xff) => NyxNewBits::NewEdge,
+                    _ => std::cmp::max(result, NyxNewBits::NewCount),
+                };
+            }
+        }
+        result
+    }
+
+    /* Number of edges which have been observed by at least one worker. */
+    pub fn count_covered(&self) -> usize {
+        let mut covered = 0;
+        for (i, word) in self.words.iter().enumerate() {
+            let bytes = word.load(Ordering::Relaxed).to_ne_bytes();
+            let len = std::cmp::min(WORD_SIZE, self.size - i * WORD_SIZE);
+            covered += bytes[..len].iter().filter(|x| **x != 0xff).count();
+        }
+        covered
+    }
+}
+
+impl Drop for GlobalCoverageMap {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.words.as_ptr() as *mut libc::c_void, self.words.len() * WORD_SIZE);
+        }
+    }
+}
+
+impl NyxProcess {
+
+    /* Classifies the hit counts of the last execution (see classify_bitmap()) and merges them into the global map.
+     * Can be combined with update_coverage() for the same execution.
+     */
+    pub fn update_global_coverage(&mut self, map: &GlobalCoverageMap) -> NyxNewBits {
+        map.merge(self.classify_bitmap())
+    }
+}
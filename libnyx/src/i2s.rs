@@ -0,0 +1,162 @@
+/*
+    libnyx input-to-state (I2S) mutations based on redqueen results
+
+    Copyright (C) 2021 Sergej Schumilo
+    This file is part of libnyx.
+
+    libnyx is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 2 of the License, or
+    (at your option) any later version.
+    libnyx is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+    You should have received a copy of the GNU General Public License
+    along with libnyx.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use super::*;
+use crate::redqueen::RedqueenCmp;
+
+/* Operands are also searched for with small arithmetic offsets (the target might compare x+1, x-1, ...). */
+const I2S_ARITH_RANGE: i64 = 1;
+
+/* A patched input which might satisfy (or flip) one of the observed comparisons. */
+#[derive(Debug, Clone)]
+pub struct I2sCandidate {
+    pub input: Vec<u8>,
+
+    /* position and size of the patched bytes */
+    pub offset: usize,
+    pub size: usize,
+
+    /* the comparison this candidate is targeting */
+    pub cmp: RedqueenCmp,
+}
+
+fn encode(value: u64, size: usize, big_endian: bool) -> Vec<u8> {
+    let bytes = value.to_le_bytes();
+    let mut data = bytes[..size].to_vec();
+    if big_endian {
+        data.reverse();
+    }
+    data
+}
+
+fn mask(value: i128, size: usize) -> u64 {
+    match size {
+        8 => value as u64,
+        _ => (value as u64) & ((1_u64 << (size * 8)) - 1),
+    }
+}
+
+/* Returns (pattern, replacement) pairs for replacing the observed operand "from" with "to". */
+fn replacements(cmp: &RedqueenCmp, from: &[u8], to: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+    /* string compares and integer operands wider than 64 bits (e.g. vector compares) are replaced as byte strings */
+    let (size, from, to) = match (cmp.value(from), cmp.value(to)) {
+        (Some(x), Some(y)) => (from.len(), x as i128, y as i128),
+        _ => return vec![(from.to_vec(), to.to_vec())],
+    };
+
+    let mut pairs = vec![];
+    for big_endian in [false, true] {
+        if size == 1 && big_endian {
+            break;
+        }
+
+        /* the input contains the operand with an arithmetic offset */
+        for delta in -I2S_ARITH_RANGE..=I2S_ARITH_RANGE {
+            let delta = delta as i128;
+            pairs.push((encode(mask(from + delta, size), size, big_endian), encode(mask(to + delta, size), size, big_endian)));
+        }
+
+        /* values next to the other operand (flips <, <=, >, >= compares) */
+        for delta in [-1, 1] {
+            pairs.push((encode(mask(from, size), size, big_endian), encode(mask(to + delta, size), size, big_endian)));
+        }
+    }
+    pairs
+}
+
+fn find_all(data: &[u8], pattern: &[u8]) -> Vec<usize> {
+    if pattern.is_empty() || pattern.len() > data.len() {
+        return vec![];
+    }
+    data.windows(pattern.len())
+        .enumerate()
+        .filter(|(_, x)| *x == pattern)
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/* Creates I2S candidates: every occurrence of an operand (little- or big-endian, with small arithmetic
+ * offsets) in the input is replaced by the other operand. Duplicates and no-op patches are dropped.
+ */
+pub fn i2s_candidates(input: &[u8], cmps: &[RedqueenCmp]) -> Vec<I2sCandidate> {
+    let mut candidates = vec![];
+    let mut known = HashSet::new();
+
+    for cmp in cmps.iter() {
+        if cmp.lhs == cmp.rhs {
+            continue;
+        }
+
+        for (from, to) in [(&cmp.lhs, &cmp.rhs), (&cmp.rhs, &cmp.lhs)] {
+            for (pattern, replacement) in replacements(cmp, from, to) {
+                if pattern == replacement {
+                    continue;
+                }
+                for offset in find_all(input, &pattern) {
+                    let mut patched = input.to_vec();
+                    patched[offset..offset + replacement.len()].copy_from_slice(&replacement);
+                    if known.insert(patched.clone()) {
+                        candidates.push(I2sCandidate {
+                            input: patched,
+                            offset,
+                            size: replacement.len(),
+                            cmp: cmp.clone(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    candidates
+}
+
+fn relation(cmp: &RedqueenCmp) -> Ordering {
+    match (cmp.lhs_value(), cmp.rhs_value()) {
+        (Some(lhs), Some(rhs)) => lhs.cmp(&rhs),
+        _ => cmp.lhs.cmp(&cmp.rhs),
+    }
+}
+
+impl NyxProcess {
+
+    /* Runs a candidate in redqueen mode and checks whether the outcome of the targeted comparison
+     * (==, <, >) has changed. The input buffer contains the candidate afterwards.
+     */
+    pub fn i2s_verify_candidate(&mut self, candidate: &I2sCandidate) -> Result<bool, NyxError> {
+        self.set_input(&candidate.input, candidate.input.len() as u32);
+        let cmps = self.exec_redqueen()?;
+
+        let expected = relation(&candidate.cmp);
+        Ok(cmps.iter()
+            .filter(|x| x.addr == candidate.cmp.addr)
+            .any(|x| relation(x) != expected))
+    }
+
+    /* Keeps only those candidates which flip their targeted comparison (one redqueen execution per candidate). */
+    pub fn i2s_verify(&mut self, candidates: Vec<I2sCandidate>) -> Result<Vec<I2sCandidate>, NyxError> {
+        let mut verified = vec![];
+        for candidate in candidates.into_iter() {
+            if self.i2s_verify_candidate(&candidate)? {
+                verified.push(candidate);
+            }
+        }
+        Ok(verified)
+    }
+}
@@ -0,0 +1,100 @@
+/*
+    libnyx ijon / interpreter feedback
+
+    Copyright (C) 2021 Sergej Schumilo
+    This file is part of libnyx.
+
+    libnyx is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 2 of the License, or
+    (at your option) any later version.
+    libnyx is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+    You should have received a copy of the GNU General Public License
+    along with libnyx.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use fuzz_runner::nyx::ijon_data::{IJON_MAX_DATA_OFFSET, IJON_MAX_SLOTS};
+
+use super::*;
+
+const SLOT_SIZE: usize = std::mem::size_of::<u64>();
+
+/* Campaign-wide maximum of every ijon max slot. */
+#[derive(Debug, Clone)]
+pub struct IjonAggregator {
+    pub max: Vec<u64>,
+}
+
+impl IjonAggregator {
+
+    pub fn new(slots: usize) -> IjonAggregator {
+        IjonAggregator { max: vec![0; slots] }
+    }
+
+    /* Creates an aggregator for all max slots provided by the ijon buffer of the given process. */
+    pub fn for_process(process: &NyxProcess) -> IjonAggregator {
+        IjonAggregator::new(process.ijon_max_slots())
+    }
+
+    /* Merges the max values of a single execution and returns the slots which have been improved. */
+    pub fn update(&mut self, values: &[u64]) -> Vec<usize> {
+        let mut improved = vec![];
+        for (slot, (max, value)) in self.max.iter_mut().zip(values.iter()).enumerate() {
+            if value > max {
+                *max = *value;
+                improved.push(slot);
+            }
+        }
+        improved
+    }
+
+    pub fn reset(&mut self) {
+        self.max.fill(0);
+    }
+}
+
+impl NyxProcess {
+
+    /* Enables (default) or disables clearing the ijon buffer before every execution. If disabled,
+     * the agent's values accumulate across executions.
+     */
+    pub fn set_ijon_reset(&mut self, enable: bool) {
+        self.ijon_reset = enable;
+    }
+
+    /* Number of opcodes executed by the target interpreter (InterpreterData.executed_opcode_num). */
+    pub fn ijon_executed_opcode_num(&self) -> u32 {
+        u32::from_ne_bytes(self.ijon_buffer()[..4].try_into().unwrap())
+    }
+
+    /* Number of max slots in the ijon buffer (IjonData.max_data; 256 for the default buffer size). */
+    pub fn ijon_max_slots(&self) -> usize {
+        (self.ijon_buffer().len().saturating_sub(IJON_MAX_DATA_OFFSET) / SLOT_SIZE).min(IJON_MAX_SLOTS)
+    }
+
+    /* Returns the value of an ijon max slot (None if the slot does not exist). */
+    pub fn ijon_max(&self, slot: usize) -> Option<u64> {
+        if slot >= self.ijon_max_slots() {
+            return None;
+        }
+        let offset = IJON_MAX_DATA_OFFSET + slot * SLOT_SIZE;
+        let data = self.ijon_buffer().get(offset..offset + SLOT_SIZE)?;
+        Some(u64::from_ne_bytes(data.try_into().unwrap()))
+    }
+
+    /* Returns the values of all ijon max slots. */
+    pub fn ijon_max_values(&self) -> Vec<u64> {
+        self.ijon_buffer()[IJON_MAX_DATA_OFFSET..]
+            .chunks_exact(SLOT_SIZE)
+            .take(self.ijon_max_slots())
+            .map(|x| u64::from_ne_bytes(x.try_into().unwrap()))
+            .collect()
+    }
+
+    /* Merges the ijon max values of the last execution into an aggregator (returns the improved slots). */
+    pub fn update_ijon(&self, aggregator: &mut IjonAggregator) -> Vec<usize> {
+        aggregator.update(&self.ijon_max_values())
+    }
+}
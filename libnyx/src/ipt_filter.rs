@@ -0,0 +1,90 @@
+/*
+    libnyx Intel-PT IP filter resolution
+
+    Copyright (C) 2021 Sergej Schumilo
+    This file is part of libnyx.
+
+    libnyx is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 2 of the License, or
+    (at your option) any later version.
+    libnyx is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+    You should have received a copy of the GNU General Public License
+    along with libnyx.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use config::IptFilterSpec;
+
+use super::*;
+use crate::symbolizer::{Module, Symbolizer};
+
+/* glob matching with * (any sequence) and ? (any character) */
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => glob_match(&pattern[1..], name) || (!name.is_empty() && glob_match(pattern, &name[1..])),
+        (Some(b'?'), Some(_)) => glob_match(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => glob_match(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+fn invalid(spec: &IptFilterSpec, msg: String) -> ConfigError {
+    ConfigError::InvalidValue{ field: "ipt_filters", msg: format!("{}: {}", spec, msg) }
+}
+
+fn find_module<'a>(symbolizer: &'a Symbolizer, spec: &IptFilterSpec, name: &str) -> Result<&'a Module, ConfigError> {
+    match symbolizer.modules.iter().find(|x| x.name() == name) {
+        Some(x) => Ok(x),
+        None => Err(invalid(spec, format!("no (non-PIE) ELF executable named {} found in the sharedir", name))),
+    }
+}
+
+/* Resolves a filter spec to an address range [a, b) (not yet page-aligned). */
+pub fn resolve_ipt_filter(symbolizer: &Symbolizer, spec: &IptFilterSpec) -> Result<(u64, u64), ConfigError> {
+    match spec {
+        IptFilterSpec::Range(a, b) => Ok((*a, *b)),
+        IptFilterSpec::TextOf(name) => {
+            let module = find_module(symbolizer, spec, name)?;
+            Ok((module.start, module.end))
+        },
+        IptFilterSpec::Section(name, section) => {
+            let module = find_module(symbolizer, spec, name)?;
+            module.section_range(section).ok_or_else(|| invalid(spec, format!("{} has no section {}", name, section)))
+        },
+        IptFilterSpec::SymbolRange(pattern) => {
+            let mut result = None;
+            for module in symbolizer.modules.iter() {
+                let functions = module.find_functions(|x| glob_match(pattern.as_bytes(), x.as_bytes()));
+                if functions.is_empty() {
+                    continue;
+                }
+                if result.is_some() {
+                    return Err(invalid(spec, "matching functions found in more than one binary".to_string()));
+                }
+                result = functions.iter()
+                    .map(|x| module.function_range(*x))
+                    .reduce(|(a0, b0), (a1, b1)| (a0.min(a1), b0.max(b1)));
+            }
+            result.ok_or_else(|| invalid(spec, "no matching function symbols found".to_string()))
+        },
+    }
+}
+
+/* Resolves all symbolic filters of the config (see IptFilterSpec) against the binaries in the sharedir. */
+pub fn resolve_ipt_filters(config: &mut NyxConfig) -> Result<(), ConfigError> {
+    if config.config.fuzz.ipt_filter_specs.is_empty() {
+        return Ok(());
+    }
+
+    let symbolizer = Symbolizer::from_sharedir(config);
+    let ranges = config.config.fuzz.ipt_filter_specs.iter()
+        .map(|x| resolve_ipt_filter(&symbolizer, x))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    config.config.fuzz.add_ipt_filters(&ranges)?;
+    config.config.fuzz.ipt_filter_specs.clear();
+    Ok(())
+}
@@ -17,18 +17,34 @@
  */
 extern crate libc;
 
-use config::{Config, FuzzRunnerConfig, QemuNyxRole};
+use config::{Config, FuzzRunnerConfig, QemuNyxRole, ShmBacking};
+pub use config::{ConfigError, RespawnPolicy};
 
-use fuzz_runner::nyx::qemu_process::QemuProcess;
-use fuzz_runner::nyx::aux_buffer::{NYX_SUCCESS, NYX_CRASH, NYX_TIMEOUT, NYX_INPUT_WRITE, NYX_ABORT};
+use fuzz_runner::FuzzRunner;
+pub use fuzz_runner::NyxError;
+use fuzz_runner::nyx::aux_buffer::{NYX_SUCCESS, NYX_CRASH, NYX_TIMEOUT, NYX_INPUT_WRITE};
 use libc::fcntl;
 
 use std::fmt;
 
+pub mod corpus;
+pub mod coverage_export;
+pub mod coverage_map;
+pub mod crash;
+pub mod crash_dedup;
 pub mod ffi;
+pub mod global_coverage;
+pub mod i2s;
+pub mod ijon;
+pub mod ipt_filter;
+pub mod redqueen;
+pub mod symbolizer;
+pub mod trace;
+
+pub use redqueen::{RedqueenCmp, NyxCmpKind};
 
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum NyxReturnValue {
     Normal,
     Crash,
@@ -38,6 +54,7 @@ pub enum NyxReturnValue {
     Error,
     IoError,    // QEMU process has died for some reason
     Abort,      // Abort hypercall called
+    HostTimeout, // QEMU process did not respond in time and has been killed
 }
 
 #[repr(C)]
@@ -48,25 +65,125 @@ pub enum NyxProcessRole {
     Child,
 }
 
+#[repr(C)]
+#[derive(Debug)]
+pub enum NyxShmBacking {
+    Tmpfs,
+    Hugetlbfs,
+    Memfd,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NyxCoverageMode {
+    IntelPT,        // Intel-PT tracing (KVM-Nyx and libxdc)
+    CompileTime,    // agent writes the coverage bitmap itself (compile-time instrumentation)
+}
+
+/* Features supported by the agent and the buffer sizes negotiated during startup. */
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct NyxCapabilities {
+    pub coverage_mode: NyxCoverageMode,
+    pub redqueen: bool,
+    pub ijon: bool,
+    pub agent_timeout_detection: bool,
+    pub input_buffer_size: usize,
+    pub bitmap_size: usize,
+    pub ijon_buffer_size: usize,
+}
+
 impl fmt::Display for NyxReturnValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 
         let nyx_return_value_str = match self {
             NyxReturnValue::Normal                => "Normal",
             NyxReturnValue::Crash                 => "Crash",
+            NyxReturnValue::Asan                  => "Asan",
             NyxReturnValue::Timeout               => "Timeout",
             NyxReturnValue::InvalidWriteToPayload => "InvalidWriteToPayload",
             NyxReturnValue::Abort                 => "Abort",
             NyxReturnValue::Error                 => "Error",
-            _                                     => "Unknown",
+            NyxReturnValue::IoError               => "IoError",
+            NyxReturnValue::HostTimeout           => "HostTimeout",
         };
 
         write!(f, "{}", nyx_return_value_str)
     }
 }
 
+/* Maps an exec error to the return value reported via the C API. */
+impl From<&NyxError> for NyxReturnValue {
+    fn from(err: &NyxError) -> Self {
+        match err {
+            NyxError::AgentAbort(_)          => NyxReturnValue::Abort,
+            NyxError::Io(_)                  => NyxReturnValue::IoError,
+            NyxError::QemuExited { .. }      => NyxReturnValue::IoError,
+            NyxError::HostTimeout            => NyxReturnValue::HostTimeout,
+            _                                => NyxReturnValue::Error,
+        }
+    }
+}
+
+/* Outcome of a single execution including the statistics reported by QEMU-Nyx via the aux buffer. */
+#[derive(Debug, Clone)]
+pub struct ExecResult {
+    pub value: NyxReturnValue,
+
+    /* crash message reported by the agent (if any) */
+    pub message: Option<String>,
+
+    pub runtime: std::time::Duration,
+    pub dirty_pages: u32,
+    pub pt_trace_size: u32,
+    pub bb_coverage: u32,
+    pub pt_overflow: bool,
+    pub reloaded: bool,
+    pub tmp_snapshot_created: bool,
+}
+
+/* C representation of ExecResult (the message can be fetched via nyx_get_aux_string()). */
+#[repr(C)]
+#[derive(Debug)]
+pub struct NyxExecResult {
+    pub value: NyxReturnValue,
+    pub message_len: u32,
+    pub runtime_sec: u32,
+    pub runtime_usec: u32,
+    pub dirty_pages: u32,
+    pub pt_trace_size: u32,
+    pub bb_coverage: u32,
+    pub pt_overflow: bool,
+    pub reloaded: bool,
+    pub tmp_snapshot_created: bool,
+}
+
+impl From<&ExecResult> for NyxExecResult {
+    fn from(result: &ExecResult) -> Self {
+        NyxExecResult {
+            value: result.value,
+            message_len: result.message.as_ref().map_or(0, |x| x.len() as u32),
+            runtime_sec: result.runtime.as_secs() as u32,
+            runtime_usec: result.runtime.subsec_micros(),
+            dirty_pages: result.dirty_pages,
+            pt_trace_size: result.pt_trace_size,
+            bb_coverage: result.bb_coverage,
+            pt_overflow: result.pt_overflow,
+            reloaded: result.reloaded,
+            tmp_snapshot_created: result.tmp_snapshot_created,
+        }
+    }
+}
+
 pub struct NyxProcess {
-    process: QemuProcess,
+    process: Box<dyn FuzzRunner>,
+    respawn_policy: Option<RespawnPolicy>,
+
+    /* clear the ijon buffer before every execution (ijon agents only) */
+    ijon_reset: bool,
+
+    /* the bitmap of the last execution has been classified in place (see classify_bitmap()) */
+    bitmap_classified: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -80,15 +197,14 @@ impl NyxConfig {
     /* Loads a given Nyx share-dir and returns a result object containing the config object.
      * The config object is later used to access the config object via specific config functions.
      */
-    pub fn load(sharedir: &str) -> Result<NyxConfig, String> {
+    pub fn load(sharedir: &str) -> Result<NyxConfig, ConfigError> {
         /* TODO: perform some additional sanity checks on the sharedir (such as checking if the bootstrap scripts exist) */
-        match Config::new_from_sharedir(&sharedir){
-            Ok(x) => Ok(NyxConfig{
-                config: x,
-                sharedir_path: sharedir.to_string()
-            }),
-            Err(x) => Err(x),
-        }
+        let mut config = NyxConfig{
+            config: Config::new_from_sharedir(&sharedir)?,
+            sharedir_path: sharedir.to_string()
+        };
+        ipt_filter::resolve_ipt_filters(&mut config)?;
+        Ok(config)
     }
 
     /* Simple debug function to print the entire config object to stdout. */
@@ -102,6 +218,14 @@ impl NyxConfig {
 
     }
 
+    /* Returns the (resolved and page-aligned) Intel-PT IP filter ranges. */
+    pub fn ipt_filters(&self) -> Vec<(u64, u64)> {
+        self.config.fuzz.ipt_filters.iter()
+            .filter(|x| x.a < x.b)
+            .map(|x| (x.a, x.b))
+            .collect()
+    }
+
     /* Returns the path to the actual sharedir. */
     pub fn sharedir_path(&self) -> String {
         self.sharedir_path.clone()
@@ -240,6 +364,48 @@ impl NyxConfig {
         return self.config.runtime.set_aux_buffer_size(size);
     }
 
+    /* Returns the deadline for QEMU-Nyx to come up after being spawned. */
+    pub fn startup_timeout(&self) -> std::time::Duration {
+        self.config.runtime.startup_timeout()
+    }
+
+    /* Sets the deadline for QEMU-Nyx to come up after being spawned (default value is 60s). */
+    pub fn set_startup_timeout(&mut self, timeout: std::time::Duration) {
+        self.config.runtime.set_startup_timeout(timeout);
+    }
+
+    /* Sets the host-side watchdog for QEMU-Nyx as a multiple of the configured timeout (default value is 0, i.e. disabled).
+     * There is no watchdog for executions without a timeout (timeout 0).
+     */
+    pub fn set_host_timeout_factor(&mut self, factor: u32) {
+        self.config.runtime.set_host_timeout_factor(factor);
+    }
+
+    /* Enables (or disables if None) automatic respawning of QEMU-Nyx after a failed execution. */
+    pub fn set_respawn_policy(&mut self, policy: Option<RespawnPolicy>) {
+        self.config.runtime.set_respawn_policy(policy);
+    }
+
+    /* Sets the backing of the shm buffers shared with QEMU-Nyx (default is tmpfs in /dev/shm).
+     * base_dir is the tmpfs / hugetlbfs mount to use (ignored for memfd; None keeps the current value).
+     */
+    pub fn set_shm_backing(&mut self, backing: NyxShmBacking, base_dir: Option<String>) {
+        let _backing = match backing {
+            NyxShmBacking::Tmpfs => ShmBacking::Tmpfs,
+            NyxShmBacking::Hugetlbfs => ShmBacking::Hugetlbfs,
+            NyxShmBacking::Memfd => ShmBacking::Memfd,
+        };
+
+        self.config.runtime.set_shm_backing(_backing, base_dir);
+    }
+
+    /* Sets the namespace of the shm work dirs (only [a-zA-Z0-9-]; default is a hash of the workdir path).
+     * Orphaned shm work dirs are only removed within the same namespace.
+     */
+    pub fn set_shm_namespace(&mut self, namespace: &str) -> bool {
+        self.config.runtime.set_shm_namespace(namespace)
+    }
+
     pub fn dict(&self) -> Vec<Vec<u8>> {
         self.config.fuzz.dict.clone()
     }
@@ -253,19 +419,60 @@ impl fmt::Display for NyxConfig {
 
 impl NyxProcess {
 
-    pub fn new(config: &mut NyxConfig, worker_id: usize) -> Result<NyxProcess, String> {
+    pub fn new(config: &mut NyxConfig, worker_id: usize) -> Result<NyxProcess, NyxError> {
 
         let sharedir = config.sharedir_path();
         config.set_worker_id(worker_id);
 
-        match fuzz_runner::nyx::qemu_process_new(sharedir.to_string(), &config.config){
+        match fuzz_runner::runner::runner_new(sharedir.to_string(), &config.config){
             Ok(x) => Ok(NyxProcess{
                 process: x,
+                respawn_policy: config.config.runtime.respawn_policy(),
+                ijon_reset: true,
+                bitmap_classified: false,
             }),
             Err(x) => Err(x),
         }
     }
 
+    /* Wraps an already spawned backend (e.g. a custom or mock runner) into a NyxProcess object. */
+    pub fn from_runner(runner: Box<dyn FuzzRunner>) -> NyxProcess {
+        NyxProcess{
+            process: runner,
+            respawn_policy: None,
+            ijon_reset: true,
+            bitmap_classified: false,
+        }
+    }
+
+    /* Enables (or disables if None) automatic respawning after a failed execution. */
+    pub fn set_respawn_policy(&mut self, policy: Option<RespawnPolicy>) {
+        self.respawn_policy = policy;
+    }
+
+    /* Kills the QEMU-Nyx process and spawns a new one using the root snapshot in the workdir
+     * (same worker id). Aux, input, bitmap and ijon buffers remain valid.
+     */
+    pub fn restart(&mut self) -> Result<(), NyxError> {
+        self.process.restart()
+    }
+
+    /* Restarts the runner according to the respawn policy. Returns the last error if all attempts have failed. */
+    fn respawn(&mut self, policy: RespawnPolicy) -> Result<(), NyxError> {
+        let mut backoff = policy.backoff;
+        let mut result = Ok(());
+        for attempt in 1..=policy.max_retries {
+            std::thread::sleep(backoff);
+            result = self.restart();
+            match result {
+                Ok(()) => return Ok(()),
+                Err(ref x) => println!("[!] libnyx: respawn attempt {}/{} failed: {}", attempt, policy.max_retries, x),
+            }
+            backoff *= 2;
+        }
+        result
+    }
+
 
     pub fn aux_buffer_as_mut_ptr(&self) -> *mut u8 {
         std::ptr::addr_of!(self.process.aux_buffer().header.magic) as *mut u8
@@ -276,31 +483,31 @@ impl NyxProcess {
     }
     
     pub fn input_buffer(&self) -> &[u8] {
-        self.process.payload
+        self.process.input_buffer()
     }
     
     pub fn input_buffer_mut(&mut self) -> &mut [u8] {
-        self.process.payload
+        self.process.input_buffer_mut()
     }
 
     pub fn input_buffer_size(&mut self) -> usize {
-        self.process.payload.len()
+        self.process.input_buffer().len()
     }
     
     pub fn bitmap_buffer(&self) -> &[u8] {
-        &self.process.bitmap[.. self.process.bitmap_size]
+        self.process.bitmap_buffer()
     }
     
     pub fn bitmap_buffer_mut(&mut self) -> &mut [u8] {
-        &mut self.process.bitmap[.. self.process.bitmap_size]
+        self.process.bitmap_buffer_mut()
     }
 
     pub fn bitmap_buffer_size(&self) -> usize {
-        self.process.bitmap_size
+        self.process.bitmap_buffer().len()
     }
 
     pub fn ijon_buffer(&self) -> &[u8] {
-        self.process.ijon_buffer
+        self.process.ijon_buffer()
     }
     
     pub fn shutdown(&mut self) {
@@ -332,6 +539,27 @@ impl NyxProcess {
         self.process.aux_buffer_mut().config.changed = 1;
     }
 
+    /* Returns the capabilities reported by the agent (fails if the agent reports an unknown coverage mode). */
+    pub fn capabilities(&self) -> Result<NyxCapabilities, NyxError> {
+        let cap = &self.process.aux_buffer().cap;
+
+        let coverage_mode = match cap.agent_trace_bitmap {
+            0 => NyxCoverageMode::IntelPT,
+            1 => NyxCoverageMode::CompileTime,
+            x => return Err(NyxError::AuxBufferMismatch(format!("unknown aux_buffer.cap.agent_trace_bitmap value: {}", x))),
+        };
+
+        Ok(NyxCapabilities {
+            coverage_mode,
+            redqueen: cap.redqueen != 0,
+            ijon: cap.agent_ijon_trace_bitmap != 0,
+            agent_timeout_detection: cap.agent_timeout_detection != 0,
+            input_buffer_size: self.process.input_buffer().len(),
+            bitmap_size: self.process.bitmap_buffer().len(),
+            ijon_buffer_size: self.process.ijon_buffer().len(),
+        })
+    }
+
     pub fn aux_misc(&self) -> Vec<u8>{
         self.process.aux_buffer().misc_slice().to_vec()
     }
@@ -349,26 +577,59 @@ impl NyxProcess {
         String::from_utf8_lossy(&self.process.aux_buffer().misc_data_slice()[0..len as usize]).to_string()
     }
      
-    pub fn exec(&mut self) -> NyxReturnValue {
-        match self.process.send_payload(){
-            Err(_) =>  NyxReturnValue::IoError,
-            Ok(_) => {
-                match self.process.aux_buffer().result.exec_result_code {
-                    NYX_SUCCESS     => NyxReturnValue::Normal,
-                    NYX_CRASH       => NyxReturnValue::Crash,
-                    NYX_TIMEOUT     => NyxReturnValue::Timeout,
-                    NYX_INPUT_WRITE => NyxReturnValue::InvalidWriteToPayload,
-                    NYX_ABORT       => NyxReturnValue::Abort,
-                    _                  => NyxReturnValue::Error,
-                }
+    /* Runs the current input. Errors (agent abort, QEMU-Nyx died, ...) are returned as NyxError,
+     * use NyxReturnValue::from(&err) to map them to the values reported via the C API.
+     * If a respawn policy is set, the runner is restarted before the error is returned.
+     * Crashes reported by KASAN or ASAN are returned as NyxReturnValue::Asan (see crash_info()).
+     */
+    pub fn exec(&mut self) -> Result<NyxReturnValue, NyxError> {
+        self.bitmap_classified = false;
+        if self.ijon_reset && self.process.aux_buffer().cap.agent_ijon_trace_bitmap != 0 {
+            self.process.ijon_buffer_mut().fill(0);
+        }
+        if let Err(x) = self.process.exec() {
+            /* the failed execution is still reported (unless the runner cannot be respawned) */
+            if let Some(policy) = self.respawn_policy {
+                self.respawn(policy)?;
             }
+            return Err(x);
+        }
+        match self.process.aux_buffer().result.exec_result_code {
+            NYX_SUCCESS     => Ok(NyxReturnValue::Normal),
+            NYX_CRASH       => Ok(self.crash_info().class.return_value()),
+            NYX_TIMEOUT     => Ok(NyxReturnValue::Timeout),
+            NYX_INPUT_WRITE => Ok(NyxReturnValue::InvalidWriteToPayload),
+            x               => Err(NyxError::UnknownExecCode(x)),
         }
     }
 
+    /* Runs the current input and returns the outcome together with the statistics of this execution. */
+    pub fn exec_result(&mut self) -> Result<ExecResult, NyxError> {
+        let value = self.exec()?;
+
+        let message = match value {
+            NyxReturnValue::Crash | NyxReturnValue::Asan if self.process.aux_buffer().misc.len != 0 => Some(self.aux_string()),
+            _ => None,
+        };
+
+        let result = &self.process.aux_buffer().result;
+        Ok(ExecResult {
+            value,
+            message,
+            runtime: std::time::Duration::from_secs(result.runtime_sec as u64) + std::time::Duration::from_micros(result.runtime_usec as u64),
+            dirty_pages: result.dirty_pages,
+            pt_trace_size: result.pt_trace_size,
+            bb_coverage: result.bb_coverage,
+            pt_overflow: result.pt_overflow != 0,
+            reloaded: result.reloaded != 0,
+            tmp_snapshot_created: result.tmp_snapshot_created != 0,
+        })
+    }
+
     pub fn set_input_ptr(&mut self, buffer: *const u8, size: u32) {
         unsafe{
-            std::ptr::copy(&size, self.process.payload.as_mut_ptr() as *mut u32, 1 as usize);
-            std::ptr::copy(buffer, self.process.payload[std::mem::size_of::<u32>()..].as_mut_ptr(), std::cmp::min(size as usize, self.input_buffer_size()));
+            std::ptr::copy(&size, self.process.input_buffer_mut().as_mut_ptr() as *mut u32, 1 as usize);
+            std::ptr::copy(buffer, self.process.input_buffer_mut()[std::mem::size_of::<u32>()..].as_mut_ptr(), std::cmp::min(size as usize, self.input_buffer_size()));
         }
     }
     
@@ -391,3 +652,7 @@ impl NyxProcess {
 pub fn remove_work_dir(workdir: &str) -> Result<(), String> {
     fuzz_runner::nyx::qemu_process::remove_workdir_safe(workdir)
 }
+
+#[cfg(test)]
+#[path = "tests/tests.rs"]
+mod tests;
@@ -0,0 +1,194 @@
+/*
+    libnyx redqueen (cmplog) support
+
+    Copyright (C) 2021 Sergej Schumilo
+    This file is part of libnyx.
+
+    libnyx is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 2 of the License, or
+    (at your option) any later version.
+    libnyx is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+    You should have received a copy of the GNU General Public License
+    along with libnyx.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::fs;
+use std::io;
+
+use super::*;
+
+/* QEMU-Nyx appends one line per observed comparison to this file (in redqueen_workdir_<id>). */
+pub const REDQUEEN_RESULTS_FILE: &str = "redqueen_results.txt";
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NyxCmpKind {
+    Cmp,    // cmp instruction
+    Sub,    // sub instruction
+    Lea,    // lea instruction (compare against a displacement)
+    Str,    // strcmp / memcmp hook
+}
+
+/* A single comparison observed in redqueen mode.
+ * lhs and rhs are stored as printed by QEMU-Nyx: integer operands in big-endian byte order,
+ * string operands in memory order.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedqueenCmp {
+    pub addr: u64,
+    pub kind: NyxCmpKind,
+
+    /* operand size in bytes */
+    pub size: usize,
+    pub lhs: Vec<u8>,
+    pub rhs: Vec<u8>,
+
+    /* rhs is an immediate value */
+    pub is_imm: bool,
+}
+
+impl RedqueenCmp {
+
+    /* Returns the numeric value of an integer operand (None for string compares). */
+    pub(crate) fn value(&self, operand: &[u8]) -> Option<u64> {
+        if self.kind == NyxCmpKind::Str || operand.len() > 8 {
+            return None;
+        }
+        Some(operand.iter().fold(0, |acc, b| (acc << 8) | *b as u64))
+    }
+
+    pub fn lhs_value(&self) -> Option<u64> {
+        self.value(&self.lhs)
+    }
+
+    pub fn rhs_value(&self) -> Option<u64> {
+        self.value(&self.rhs)
+    }
+}
+
+/* maximum operand size reported via the C API (longer string operands are truncated) */
+pub const NYX_REDQUEEN_MAX_OPERAND_SIZE: usize = 64;
+
+/* C representation of RedqueenCmp. */
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct NyxRedqueenCmp {
+    pub addr: u64,
+    pub kind: NyxCmpKind,
+    pub size: u32,
+    pub is_imm: bool,
+    pub lhs: [u8; NYX_REDQUEEN_MAX_OPERAND_SIZE],
+    pub rhs: [u8; NYX_REDQUEEN_MAX_OPERAND_SIZE],
+}
+
+impl From<&RedqueenCmp> for NyxRedqueenCmp {
+    fn from(cmp: &RedqueenCmp) -> Self {
+        let mut lhs = [0; NYX_REDQUEEN_MAX_OPERAND_SIZE];
+        let mut rhs = [0; NYX_REDQUEEN_MAX_OPERAND_SIZE];
+        let size = std::cmp::min(cmp.size, NYX_REDQUEEN_MAX_OPERAND_SIZE);
+        lhs[..size].copy_from_slice(&cmp.lhs[..size]);
+        rhs[..size].copy_from_slice(&cmp.rhs[..size]);
+
+        NyxRedqueenCmp {
+            addr: cmp.addr,
+            kind: cmp.kind,
+            size: size as u32,
+            is_imm: cmp.is_imm,
+            lhs,
+            rhs,
+        }
+    }
+}
+
+impl From<&NyxRedqueenCmp> for RedqueenCmp {
+    fn from(cmp: &NyxRedqueenCmp) -> Self {
+        let size = std::cmp::min(cmp.size as usize, NYX_REDQUEEN_MAX_OPERAND_SIZE);
+        RedqueenCmp {
+            addr: cmp.addr,
+            kind: cmp.kind,
+            size,
+            lhs: cmp.lhs[..size].to_vec(),
+            rhs: cmp.rhs[..size].to_vec(),
+            is_imm: cmp.is_imm,
+        }
+    }
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    s.as_bytes().chunks(2).map(|x| match x.len() {
+        2 => u8::from_str_radix(std::str::from_utf8(x).ok()?, 16).ok(),
+        _ => None,
+    }).collect()
+}
+
+/* Parses a single result line ("<addr>\t\t <kind> <bits>\t<lhs>-<rhs>[ IMM]"). */
+fn parse_redqueen_line(line: &str) -> Option<RedqueenCmp> {
+    let mut tokens = line.split_whitespace();
+
+    let addr = u64::from_str_radix(tokens.next()?, 16).ok()?;
+    let kind = match tokens.next()? {
+        "CMP" => NyxCmpKind::Cmp,
+        "SUB" => NyxCmpKind::Sub,
+        "LEA" => NyxCmpKind::Lea,
+        "STR" => NyxCmpKind::Str,
+        _ => return None,
+    };
+    let bits: usize = tokens.next()?.parse().ok()?;
+    let (lhs, rhs) = tokens.next()?.split_once('-')?;
+    let is_imm = tokens.next() == Some("IMM");
+
+    let lhs = decode_hex(lhs)?;
+    let rhs = decode_hex(rhs)?;
+    if lhs.is_empty() || lhs.len() * 8 != bits || rhs.len() * 8 != bits {
+        return None;
+    }
+
+    Some(RedqueenCmp {
+        addr,
+        kind,
+        size: bits / 8,
+        lhs,
+        rhs,
+        is_imm,
+    })
+}
+
+/* Parses the content of a redqueen results file (malformed lines are skipped). */
+pub fn parse_redqueen_results(data: &str) -> Vec<RedqueenCmp> {
+    data.lines().filter_map(parse_redqueen_line).collect()
+}
+
+impl NyxProcess {
+
+    /* Runs the current input in redqueen mode and returns the comparisons observed by QEMU-Nyx.
+     * Redqueen mode is disabled again for the following executions.
+     */
+    pub fn exec_redqueen(&mut self) -> Result<Vec<RedqueenCmp>, NyxError> {
+        let results_path = match self.process.redqueen_workdir() {
+            Some(x) => format!("{}/{}", x, REDQUEEN_RESULTS_FILE),
+            None => return Err(NyxError::InvalidConfig("redqueen mode is not supported by this runner".to_string())),
+        };
+
+        /* QEMU-Nyx appends to the results file */
+        match fs::remove_file(&results_path) {
+            Err(x) if x.kind() != io::ErrorKind::NotFound => return Err(NyxError::Io(x)),
+            _ => {},
+        }
+
+        self.option_set_redqueen_mode(true);
+        self.option_apply();
+        let result = self.exec();
+        self.option_set_redqueen_mode(false);
+        self.option_apply();
+        result?;
+
+        match fs::read(&results_path) {
+            Ok(x) => Ok(parse_redqueen_results(&String::from_utf8_lossy(&x))),
+            Err(x) if x.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+            Err(x) => Err(NyxError::Io(x)),
+        }
+    }
+}
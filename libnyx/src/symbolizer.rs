@@ -0,0 +1,368 @@
+/*
+    libnyx address symbolization
+
+    Copyright (C) 2021 Sergej Schumilo
+    This file is part of libnyx.
+
+    libnyx is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 2 of the License, or
+    (at your option) any later version.
+    libnyx is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+    You should have received a copy of the GNU General Public License
+    along with libnyx.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+
+use addr2line::gimli::{EndianRcSlice, RunTimeEndian};
+use addr2line::object::{self, Object, ObjectSection, ObjectSegment, ObjectSymbol, SymbolKind};
+
+use super::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: u32,
+}
+
+/* A symbolized address (function + offset and source location if available). */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub addr: u64,
+    pub module: String,
+    pub function: Option<String>,
+    pub offset: u64,
+    pub location: Option<SourceLocation>,
+}
+
+/* "func+0x12 (file.c:34)" or "module+0x1234" if the function is unknown */
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.function {
+            Some(ref function) => write!(f, "{}+{:#x}", function, self.offset)?,
+            None => write!(f, "{}+{:#x}", Path::new(&self.module).file_name().unwrap_or_default().to_string_lossy(), self.offset)?,
+        }
+        if let Some(ref location) = self.location {
+            write!(f, " ({}:{})", location.file, location.line)?;
+        }
+        Ok(())
+    }
+}
+
+/* An ELF binary (or a kernel described by its System.map) and the (guest) address range of its code. */
+pub struct Module {
+    pub path: String,
+    pub start: u64,
+    pub end: u64,
+
+    /* lowest address of all loadable segments */
+    pub image_start: u64,
+
+    /* load address of position independent binaries (added to all ELF addresses) */
+    pub base: u64,
+
+    /* function symbols (address, size, name) sorted by address; size is 0 if unknown */
+    symbols: Vec<(u64, u64, String)>,
+
+    /* allocated sections (name, start, end) */
+    sections: Vec<(String, u64, u64)>,
+    dwarf: Option<addr2line::Context<EndianRcSlice<RunTimeEndian>>>,
+}
+
+impl Module {
+
+    /* Loads an ELF file which is mapped at the given base address (0 for non-PIE binaries and kernels). */
+    pub fn load(path: &str, base: u64) -> io::Result<Module> {
+        let data = fs::read(path)?;
+        let elf = object::File::parse(&*data).map_err(|x| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, x)))?;
+
+        let image_start = elf.segments().map(|x| x.address()).min().unwrap_or(0);
+
+        /* executable PT_LOAD segments */
+        let (start, end) = elf.segments()
+            .filter(|x| match x.flags() {
+                object::SegmentFlags::Elf { p_flags } => p_flags & object::elf::PF_X != 0,
+                _ => false,
+            })
+            .fold((u64::MAX, 0), |(start, end), x| (start.min(x.address()), end.max(x.address() + x.size())));
+
+        if start >= end {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}: no executable segments", path)));
+        }
+
+        /* .symtab is missing in stripped binaries -> fall back to .dynsym */
+        let mut symbols: Vec<(u64, u64, String)> = elf.symbols().chain(elf.dynamic_symbols())
+            .filter(|x| x.kind() == SymbolKind::Text && x.address() != 0)
+            .filter_map(|x| Some((base + x.address(), x.size(), x.name().ok()?.to_string())))
+            .collect();
+        symbols.sort();
+        symbols.dedup_by_key(|x| x.0);
+
+        let sections = elf.sections()
+            .filter(|x| x.address() != 0 && x.size() != 0)
+            .filter_map(|x| Some((x.name().ok()?.to_string(), base + x.address(), base + x.address() + x.size())))
+            .collect();
+
+        Ok(Module {
+            path: path.to_string(),
+            start: base + start,
+            end: base + end,
+            image_start: base + image_start,
+            base,
+            symbols,
+            sections,
+            dwarf: addr2line::Context::new(&elf).ok(),
+        })
+    }
+
+    /* Loads the text symbols of a kernel from its System.map ("<addr> <type> <name>" per line). */
+    pub fn load_system_map(path: &str) -> io::Result<Module> {
+        let content = fs::read_to_string(path)?;
+
+        let mut symbols: Vec<(u64, u64, String)> = content.lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let addr = u64::from_str_radix(fields.next()?, 16).ok()?;
+                match fields.next()? {
+                    "T" | "t" | "W" | "w" => Some((addr, 0, fields.next()?.to_string())),
+                    _ => None,
+                }
+            })
+            .collect();
+        symbols.sort();
+        symbols.dedup_by_key(|x| x.0);
+
+        let start = match symbols.first() {
+            Some(x) => x.0,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}: no text symbols", path))),
+        };
+        /* the size of the last function is unknown */
+        let end = symbols.iter().find(|x| x.2 == "_etext").map_or(symbols[symbols.len() - 1].0 + 1, |x| x.0);
+
+        Ok(Module {
+            path: path.to_string(),
+            start,
+            end,
+            image_start: start,
+            base: 0,
+            symbols,
+            sections: vec![],
+            dwarf: None,
+        })
+    }
+
+    pub fn contains(&self, addr: u64) -> bool {
+        addr >= self.start && addr < self.end
+    }
+
+    /* Returns the function containing the given address and the offset into it. */
+    pub fn function(&self, addr: u64) -> Option<(&str, u64)> {
+        let index = match self.symbols.binary_search_by_key(&addr, |x| x.0) {
+            Ok(x) => x,
+            Err(0) => return None,
+            Err(x) => x - 1,
+        };
+        let (start, size, ref name) = self.symbols[index];
+        if size != 0 && addr >= start + size {
+            return None;
+        }
+        Some((name, addr - start))
+    }
+
+    /* file name of the module (used to refer to it in config.ron) */
+    pub fn name(&self) -> String {
+        Path::new(&self.path).file_name().unwrap_or_default().to_string_lossy().to_string()
+    }
+
+    /* Returns the address range [start, end) of a function (the next symbol ends functions of unknown size). */
+    pub fn function_range(&self, index: usize) -> (u64, u64) {
+        let (start, size, _) = self.symbols[index];
+        match (size, self.symbols.get(index + 1)) {
+            (0, Some(next)) => (start, next.0),
+            (0, None) => (start, self.end),
+            (size, _) => (start, start + size),
+        }
+    }
+
+    /* Returns the indices of all functions whose name matches the given predicate. */
+    pub fn find_functions<F: Fn(&str) -> bool>(&self, predicate: F) -> Vec<usize> {
+        (0..self.symbols.len()).filter(|x| predicate(&self.symbols[*x].2)).collect()
+    }
+
+    pub fn section_range(&self, name: &str) -> Option<(u64, u64)> {
+        self.sections.iter().find(|x| x.0 == name).map(|x| (x.1, x.2))
+    }
+
+    /* Returns the source file and line of a (guest) address (requires DWARF line info). */
+    pub fn source_location(&self, addr: u64) -> Option<SourceLocation> {
+        let location = self.dwarf.as_ref()?.find_location(addr.checked_sub(self.base)?).ok()??;
+        Some(SourceLocation {
+            file: location.file?.to_string(),
+            line: location.line?,
+        })
+    }
+
+    /* Returns all source lines which have code in this module (requires DWARF line info). */
+    pub fn source_lines(&self) -> Vec<SourceLocation> {
+        let rows = match self.dwarf.as_ref().and_then(|x| x.find_location_range(0, u64::MAX).ok()) {
+            Some(x) => x,
+            None => return vec![],
+        };
+        rows.filter_map(|(_, _, location)| Some(SourceLocation {
+                file: location.file?.to_string(),
+                line: location.line.filter(|x| *x != 0)?,
+            }))
+            .collect()
+    }
+}
+
+/* Returns the ELF type (ET_EXEC, ET_DYN, ...) of a file. Checks the ELF header only (the sharedir
+ * might contain large non-ELF files).
+ */
+fn elf_type(path: &str) -> Option<u16> {
+    let mut header = [0_u8; 18];
+    match fs::File::open(path).and_then(|mut x| x.read_exact(&mut header)) {
+        Ok(_) if &header[..4] == b"\x7fELF" => Some(u16::from_le_bytes([header[16], header[17]])),
+        _ => None,
+    }
+}
+
+/* Maps guest addresses to the binaries of a target. */
+#[derive(Default)]
+pub struct Symbolizer {
+    pub modules: Vec<Module>,
+}
+
+impl Symbolizer {
+
+    pub fn new() -> Symbolizer {
+        Symbolizer { modules: vec![] }
+    }
+
+    /* Loads all non-PIE ELF executables found in the sharedir. For QemuKernel targets the kernel
+     * symbols are taken from the kernel image or a vmlinux (in the sharedir or next to the kernel
+     * image) and, if no ELF kernel is available, from a System.map. Position independent binaries
+     * (ET_DYN) are skipped with a warning; they have to be added with their load address via add_module().
+     */
+    pub fn from_sharedir(config: &NyxConfig) -> Symbolizer {
+        let mut symbolizer = Symbolizer::new();
+        let sharedir = config.sharedir_path();
+
+        let mut paths = vec![];
+        if let FuzzRunnerConfig::QemuKernel(ref x) = config.config.runner {
+            let kernel_dir = Path::new(&x.kernel).parent().map(|x| x.to_string_lossy().to_string()).unwrap_or_default();
+
+            let vmlinux = [x.kernel.clone(), format!("{}/vmlinux", kernel_dir), format!("{}/vmlinux", sharedir)]
+                .into_iter()
+                .find(|x| elf_type(x) == Some(object::elf::ET_EXEC));
+            match vmlinux {
+                Some(x) => paths.push(x),
+                None => {
+                    let system_map = [format!("{}/System.map", sharedir), format!("{}/System.map", kernel_dir)]
+                        .into_iter()
+                        .find(|x| Path::new(x).is_file());
+                    if let Some(path) = system_map {
+                        match Module::load_system_map(&path) {
+                            Ok(x) => symbolizer.modules.push(x),
+                            Err(x) => println!("[!] libnyx: cannot load {}: {}", path, x),
+                        }
+                    }
+                },
+            }
+        }
+        if let Ok(entries) = fs::read_dir(&sharedir) {
+            let mut entries: Vec<String> = entries.flatten()
+                .filter(|x| x.file_type().map(|x| x.is_file()).unwrap_or(false))
+                .map(|x| x.path().to_string_lossy().to_string())
+                .filter(|x| !paths.contains(x))
+                .collect();
+            entries.sort();
+            paths.extend(entries);
+        }
+
+        for path in paths.iter() {
+            match elf_type(path) {
+                Some(object::elf::ET_EXEC) => {
+                    if let Err(x) = symbolizer.add_module(path, 0) {
+                        println!("[!] libnyx: cannot load {}: {}", path, x);
+                    }
+                },
+                Some(object::elf::ET_DYN) => println!("[!] libnyx: skipping position independent binary {} (load address unknown)", path),
+                _ => {},
+            }
+        }
+        symbolizer
+    }
+
+    pub fn add_module(&mut self, path: &str, base: u64) -> io::Result<()> {
+        self.modules.push(Module::load(path, base)?);
+        Ok(())
+    }
+
+    /* Returns the index of the module containing the given address. */
+    pub fn module_index(&self, addr: u64) -> Option<usize> {
+        self.modules.iter().position(|x| x.contains(addr))
+    }
+
+    pub fn source_location(&self, addr: u64) -> Option<SourceLocation> {
+        self.modules[self.module_index(addr)?].source_location(addr)
+    }
+
+    /* Returns function + offset and source location of an address (None if it is not part of any module). */
+    pub fn symbolize(&self, addr: u64) -> Option<Symbol> {
+        let module = &self.modules[self.module_index(addr)?];
+        let (function, offset) = match module.function(addr) {
+            Some((name, offset)) => (Some(name.to_string()), offset),
+            None => (None, addr - module.image_start),
+        };
+
+        Some(Symbol {
+            addr,
+            module: module.path.clone(),
+            function,
+            offset,
+            location: module.source_location(addr),
+        })
+    }
+
+    /* Same as symbolize() but falls back to the plain address ("0x1234"). */
+    pub fn symbolize_str(&self, addr: u64) -> String {
+        match self.symbolize(addr) {
+            Some(x) => x.to_string(),
+            None => format!("{:#x}", addr),
+        }
+    }
+
+    /* Appends the symbol to every known address (hex number with at least 4 digits) of a message
+     * (e.g. a crash report): "RIP: 0x401136" -> "RIP: 0x401136 <foo+0x4 (foo.c:2)>".
+     */
+    pub fn annotate(&self, msg: &str) -> String {
+        let mut result = String::with_capacity(msg.len());
+        let mut rest = msg;
+
+        while let Some(pos) = rest.find(|c: char| c.is_ascii_alphanumeric() || c == '_') {
+            result.push_str(&rest[..pos]);
+            rest = &rest[pos..];
+
+            let len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            let token = &rest[..len];
+            let digits = token.strip_prefix("0x").unwrap_or(token);
+
+            result.push_str(token);
+            if digits.len() >= 4 && digits.len() <= 16 {
+                if let Some(symbol) = u64::from_str_radix(digits, 16).ok().and_then(|x| self.symbolize(x)) {
+                    result.push_str(&format!(" <{}>", symbol));
+                }
+            }
+            rest = &rest[len..];
+        }
+        result.push_str(rest);
+        result
+    }
+}
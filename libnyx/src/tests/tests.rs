@@ -0,0 +1,674 @@
+use std::fs;
+
+use fuzz_runner::nyx::mock::{MockQemuNyx, MockQemuNyxHandle, MockResponse};
+use fuzz_runner::nyx::qemu_process::QemuProcess;
+
+use crate::*;
+
+fn test_workdir(name: &str) -> String {
+    let workdir = format!("{}/libnyx_test_{}_{}", std::env::temp_dir().to_str().unwrap(), std::process::id(), name);
+    let _ = fs::remove_dir_all(&workdir);
+    workdir
+}
+
+fn spawn(mock: MockQemuNyx) -> (NyxProcess, MockQemuNyxHandle) {
+    let params = mock.qemu_params();
+    let handle = mock.spawn().unwrap();
+    let qemu = QemuProcess::new(params).unwrap();
+    (NyxProcess::from_runner(Box::new(qemu)), handle)
+}
+
+fn teardown(mut process: NyxProcess, handle: MockQemuNyxHandle, workdir: &str) {
+    process.shutdown();
+    drop(process);
+    handle.join().unwrap();
+    let _ = fs::remove_dir_all(workdir);
+}
+
+#[test]
+fn exec_result() {
+    let workdir = test_workdir("exec_result");
+    let mut mock = MockQemuNyx::new(&workdir, 1);
+    mock.push_response(MockResponse::Stats { runtime_usec: 1_500_000, dirty_pages: 23, pt_trace_size: 0x1000, bb_coverage: 42 });
+    mock.push_response(MockResponse::Crash("segfault".to_string()));
+    let (mut process, handle) = spawn(mock);
+
+    let result = process.exec_result().unwrap();
+    assert_eq!(result.value, NyxReturnValue::Normal);
+    assert_eq!(result.message, None);
+    assert_eq!(result.runtime, std::time::Duration::from_millis(1500));
+    assert_eq!((result.dirty_pages, result.pt_trace_size, result.bb_coverage), (23, 0x1000, 42));
+
+    let result = process.exec_result().unwrap();
+    assert_eq!(result.value, NyxReturnValue::Crash);
+    assert_eq!(result.message.as_deref(), Some("segfault"));
+    assert_eq!(result.dirty_pages, 0);
+
+    let ffi_result = NyxExecResult::from(&result);
+    assert_eq!(ffi_result.message_len, 8);
+
+    teardown(process, handle, &workdir);
+}
+
+#[test]
+fn capabilities() {
+    let workdir = test_workdir("capabilities");
+    let mut mock = MockQemuNyx::new(&workdir, 1);
+    mock.set_agent_trace_bitmap(1);
+    mock.set_redqueen(true);
+    mock.set_agent_coverage_bitmap_size(0x20000);
+    let (process, handle) = spawn(mock);
+
+    let cap = process.capabilities().unwrap();
+    assert_eq!(cap.coverage_mode, NyxCoverageMode::CompileTime);
+    assert!(cap.redqueen);
+    assert!(!cap.ijon);
+    assert!(!cap.agent_timeout_detection);
+    assert_eq!(cap.bitmap_size, 0x20000);
+    assert_eq!(cap.input_buffer_size, process.input_buffer().len());
+
+    teardown(process, handle, &workdir);
+}
+
+#[test]
+fn redqueen() {
+    let workdir = test_workdir("redqueen");
+    let mut mock = MockQemuNyx::new(&workdir, 1);
+    mock.push_response(MockResponse::Redqueen(concat!(
+        "4011a2\t\t CMP 32\t00001337-DEADBEEF IMM\n",
+        "4011b0\t\t STR 32\t41414141-6D616769\n",
+        "garbage\n",
+        "4011c0\t\t SUB 64\t0000000000000001-0000000000000002\n",
+    ).to_string()));
+    let (mut process, handle) = spawn(mock);
+
+    let cmps = process.exec_redqueen().unwrap();
+    assert_eq!(cmps.len(), 3);
+    assert_eq!(cmps[0], RedqueenCmp { addr: 0x4011a2, kind: NyxCmpKind::Cmp, size: 4, lhs: vec![0, 0, 0x13, 0x37], rhs: vec![0xde, 0xad, 0xbe, 0xef], is_imm: true });
+    assert_eq!(cmps[0].rhs_value(), Some(0xdeadbeef));
+    assert_eq!(cmps[1].kind, NyxCmpKind::Str);
+    assert_eq!(cmps[1].rhs, b"magi");
+    assert_eq!(cmps[1].rhs_value(), None);
+    assert_eq!(cmps[2].lhs_value(), Some(1));
+
+    /* redqueen mode is switched off again and stale results are discarded */
+    assert_eq!(process.process.aux_buffer().config.redqueen_mode, 0);
+    assert!(process.exec_redqueen().unwrap().is_empty());
+
+    teardown(process, handle, &workdir);
+}
+
+#[test]
+fn i2s() {
+    let cmp = RedqueenCmp { addr: 0x4011a2, kind: NyxCmpKind::Cmp, size: 4, lhs: vec![0, 0, 0x13, 0x37], rhs: vec![0xde, 0xad, 0xbe, 0xef], is_imm: true };
+    let input = b"AAAA\x37\x13\x00\x00BBBB\x00\x00\x13\x38".to_vec();
+
+    let candidates = i2s::i2s_candidates(&input, std::slice::from_ref(&cmp));
+    let patched: Vec<&[u8]> = candidates.iter().map(|x| &x.input[..]).collect();
+    assert!(patched.contains(&&b"AAAA\xef\xbe\xad\xdeBBBB\x00\x00\x13\x38"[..]));
+    assert!(patched.contains(&&b"AAAA\xf0\xbe\xad\xdeBBBB\x00\x00\x13\x38"[..]));
+    /* big-endian encoding with an arithmetic offset */
+    assert!(patched.contains(&&b"AAAA\x37\x13\x00\x00BBBB\xde\xad\xbe\xf0"[..]));
+    assert!(candidates.iter().all(|x| x.input.len() == input.len()));
+
+    /* integer operands wider than 64 bits are replaced as byte strings */
+    let wide = RedqueenCmp { addr: 0x4011b0, kind: NyxCmpKind::Cmp, size: 16, lhs: b"AAAA\x37\x13\x00\x00BBBB".to_vec(), rhs: b"0123456789abcdef"[..12].to_vec(), is_imm: false };
+    let wide_candidates = i2s::i2s_candidates(&input, &[wide]);
+    assert_eq!(wide_candidates.len(), 1);
+    assert_eq!(&wide_candidates[0].input[..12], b"0123456789ab");
+
+    let workdir = test_workdir("i2s");
+    let mut mock = MockQemuNyx::new(&workdir, 1);
+    mock.push_response(MockResponse::Redqueen("4011a2\t\t CMP 32\tDEADBEEF-DEADBEEF IMM\n".to_string()));
+    mock.push_response(MockResponse::Redqueen("4011a2\t\t CMP 32\t00001337-DEADBEEF IMM\n".to_string()));
+    let (mut process, handle) = spawn(mock);
+
+    let verified = process.i2s_verify(candidates[..2].to_vec()).unwrap();
+    assert_eq!(verified.len(), 1);
+    assert_eq!(verified[0].input, candidates[0].input);
+
+    teardown(process, handle, &workdir);
+}
+
+#[test]
+fn trace() {
+    let workdir = test_workdir("trace");
+    let mut mock = MockQemuNyx::new(&workdir, 1);
+    mock.push_response(MockResponse::Trace(concat!(
+        "ffffffffffffffff,401000,1\n",
+        "401000,401020,3\n",
+        "401020,401000,2\n",
+        "401000,401020,1\n",
+        "401020,ffffffffffffffff,1\n",
+    ).to_string()));
+    let (mut process, handle) = spawn(mock);
+
+    let edges = process.trace(b"input").unwrap();
+    assert_eq!(edges, vec![(0x401000, 0x401020), (0x401020, 0x401000)]);
+    assert_eq!(&process.input_buffer()[4..9], b"input");
+    assert_eq!(process.process.aux_buffer().config.trace_mode, 0);
+
+    /* the results of the previous run are discarded */
+    assert!(process.trace(b"input").unwrap().is_empty());
+
+    teardown(process, handle, &workdir);
+}
+
+#[test]
+fn coverage_export() {
+    use addr2line::object::{Object, ObjectSymbol};
+
+    let workdir = test_workdir("coverage_export");
+    let corpus_dir = format!("{}/corpus", workdir);
+    fs::create_dir_all(format!("{}/crash", corpus_dir)).unwrap();
+    fs::write(format!("{}/a", corpus_dir), b"a").unwrap();
+    fs::write(format!("{}/crash/b", corpus_dir), b"b").unwrap();
+
+    /* a non-PIE target with DWARF line info */
+    let source = format!("{}/target.c", workdir);
+    let binary = format!("{}/target", workdir);
+    fs::write(&source, "int foo(int x) {\n    return x + 1;\n}\n\nint main(void) {\n    return foo(0);\n}\n").unwrap();
+    let status = std::process::Command::new("cc").args(["-g", "-O0", "-no-pie", "-o", &binary, &source]).status().unwrap();
+    assert!(status.success());
+
+    let data = fs::read(&binary).unwrap();
+    let elf = addr2line::object::File::parse(&*data).unwrap();
+    let symbol = |name: &str| elf.symbols().find(|x| x.name() == Ok(name)).unwrap().address();
+    let (foo, main) = (symbol("foo"), symbol("main"));
+
+    let mut mock = MockQemuNyx::new(&workdir, 1);
+    mock.push_response(MockResponse::Trace(format!("{:x},{:x},1\n", main, foo)));
+    mock.push_response(MockResponse::Trace(format!("{:x},{:x},1\n{:x},{:x},1\n", main, foo, 0x1337, 0x1338)));
+    let (mut process, handle) = spawn(mock);
+
+    let report = process.corpus_coverage(&corpus_dir).unwrap();
+    assert_eq!(report.inputs, 2);
+    assert_eq!(report.addresses.get(&foo), Some(&2));
+    assert_eq!(report.addresses.get(&0x1337), Some(&1));
+
+    let mut symbolizer = symbolizer::Symbolizer::new();
+    symbolizer.add_module(&binary, 0).unwrap();
+
+    let mut drcov = vec![];
+    report.write_drcov(&symbolizer, &mut drcov).unwrap();
+    let header_len = drcov.windows(12).position(|x| x == b"BB Table: 2 ").unwrap();
+    assert!(String::from_utf8_lossy(&drcov[..header_len]).contains(&format!(", {}\n", binary)));
+    let blocks = &drcov[drcov.len() - 16..];
+    assert_eq!(u32::from_le_bytes(blocks[..4].try_into().unwrap()) as u64, main.min(foo) - symbolizer.modules[0].image_start);
+
+    let mut lcov = vec![];
+    report.write_lcov(&symbolizer, &mut lcov).unwrap();
+    let lcov = String::from_utf8(lcov).unwrap();
+    assert!(lcov.contains(&format!("SF:{}\n", source)));
+    assert!(lcov.contains("DA:1,2\n"));
+    assert!(lcov.contains("DA:5,2\n"));
+
+    /* lines which have not been executed are reported with 0 hits */
+    let record = &lcov[lcov.find(&format!("SF:{}\n", source)).unwrap()..];
+    let record = &record[..record.find("end_of_record").unwrap()];
+    assert!(record.contains("DA:2,0\n"), "{}", record);
+    let count = |key: &str| record.lines().find_map(|x| x.strip_prefix(key)).unwrap().parse::<usize>().unwrap();
+    assert_eq!(count("LH:"), 2);
+    assert!(count("LH:") < count("LF:"));
+
+    /* nothing to resolve the coverage against (e.g. only PIE binaries without load address) */
+    assert!(process.export_corpus_coverage_with(&symbolizer::Symbolizer::new(), &corpus_dir, None, None).is_err());
+
+    teardown(process, handle, &workdir);
+}
+
+#[test]
+fn symbolizer() {
+    use addr2line::object::{Object, ObjectSymbol};
+
+    let workdir = test_workdir("symbolizer");
+    fs::create_dir_all(&workdir).unwrap();
+
+    let source = format!("{}/target.c", workdir);
+    let binary = format!("{}/target", workdir);
+    fs::write(&source, "int foo(int x) {\n    return x + 1;\n}\n\nint main(void) {\n    return foo(0);\n}\n").unwrap();
+    let status = std::process::Command::new("cc").args(["-g", "-O0", "-no-pie", "-o", &binary, &source]).status().unwrap();
+    assert!(status.success());
+
+    let data = fs::read(&binary).unwrap();
+    let elf = addr2line::object::File::parse(&*data).unwrap();
+    let foo = elf.symbols().find(|x| x.name() == Ok("foo")).unwrap().address();
+
+    let mut symbolizer = symbolizer::Symbolizer::new();
+    symbolizer.add_module(&binary, 0).unwrap();
+
+    let symbol = symbolizer.symbolize(foo).unwrap();
+    assert_eq!(symbol.function.as_deref(), Some("foo"));
+    assert_eq!(symbol.offset, 0);
+    assert_eq!(symbol.to_string(), format!("foo+0x0 ({}:1)", source));
+    assert_eq!(symbolizer.symbolize_str(0x1337), "0x1337");
+    assert_eq!(symbolizer.annotate(&format!("RIP: {:#x}\n", foo)), format!("RIP: {:#x} <foo+0x0 ({}:1)>\n", foo, source));
+
+    /* kernel symbols without debug info */
+    let system_map = format!("{}/System.map", workdir);
+    fs::write(&system_map, "ffffffff81000000 T _stext\nffffffff81000100 t do_sys_open\nffffffff81002000 D some_data\nffffffff81000200 T _etext\n").unwrap();
+    symbolizer.modules.push(symbolizer::Module::load_system_map(&system_map).unwrap());
+    assert_eq!(symbolizer.symbolize_str(0xffffffff81000110), "do_sys_open+0x10");
+    assert_eq!(symbolizer.symbolize(0xffffffff81000200), None);
+
+    /* position independent binaries are skipped unless added with their load address */
+    let pie = format!("{}/target_pie", workdir);
+    let status = std::process::Command::new("cc").args(["-g", "-O0", "-fPIE", "-pie", "-o", &pie, &source]).status().unwrap();
+    assert!(status.success());
+    fs::write(format!("{}/default.ron", workdir), concat!(
+        "(runner: ForkServer((args: Some([\"/bin/true\"]), hide_output: None, input_size: Some(4096), env: None)),\n",
+        " fuzz: (workdir_path: Some(\"/tmp/workdir\"), bitmap_size: Some(65536), mem_limit: Some(512), time_limit: Some((secs: 1, nanos: 0)),\n",
+        "        seed_path: Some(\"\"), dict: Some([]), snapshot_placement: Some(none)))\n",
+    )).unwrap();
+    fs::write(format!("{}/config.ron", workdir), "(include_default_config_path: Some(\"default.ron\"), runner: ForkServer(()), fuzz: ())").unwrap();
+    let modules = symbolizer::Symbolizer::from_sharedir(&NyxConfig::load(&workdir).unwrap()).modules;
+    assert_eq!(modules.iter().map(|x| x.name()).collect::<Vec<_>>(), vec!["target"]);
+
+    let data = fs::read(&pie).unwrap();
+    let pie_foo = addr2line::object::File::parse(&*data).unwrap().symbols().find(|x| x.name() == Ok("foo")).unwrap().address();
+    let mut symbolizer = symbolizer::Symbolizer::new();
+    symbolizer.add_module(&pie, 0x555555554000).unwrap();
+    assert_eq!(symbolizer.symbolize_str(0x555555554000 + pie_foo), format!("foo+0x0 ({}:1)", source));
+    /* addresses below the load base have no source location (instead of underflowing) */
+    assert_eq!(symbolizer.modules[0].source_location(0x1000), None);
+
+    fs::remove_dir_all(&workdir).unwrap();
+}
+
+#[test]
+fn ipt_filters() {
+    use addr2line::object::{Object, ObjectSymbol};
+
+    let sharedir = test_workdir("ipt_filters");
+    fs::create_dir_all(&sharedir).unwrap();
+
+    let binary = format!("{}/target", sharedir);
+    let source = format!("{}/target.c", sharedir);
+    fs::write(&source, "int foo(int x) {\n    return x + 1;\n}\n\nint main(void) {\n    return foo(0);\n}\n").unwrap();
+    let status = std::process::Command::new("cc").args(["-g", "-O0", "-no-pie", "-o", &binary, &source]).status().unwrap();
+    assert!(status.success());
+
+    fs::write(format!("{}/default.ron", sharedir), concat!(
+        "(runner: QemuKernel((qemu_binary: Some(\"/bin/true\"), kernel: Some(\"/nonexistent/bzImage\"), ramfs: Some(\"/nonexistent/initrd\"), debug: Some(false))),\n",
+        " fuzz: (workdir_path: Some(\"/tmp/workdir\"), bitmap_size: Some(65536), mem_limit: Some(512), time_limit: Some((secs: 1, nanos: 0)),\n",
+        "        seed_path: Some(\"\"), dict: Some([]), snapshot_placement: Some(none)))\n",
+    )).unwrap();
+    let load = |filters: &str| {
+        fs::write(format!("{}/config.ron", sharedir), format!("(include_default_config_path: Some(\"default.ron\"), runner: QemuKernel(()), fuzz: (ipt_filters: [{}]))", filters)).unwrap();
+        NyxConfig::load(&sharedir)
+    };
+
+    let data = fs::read(&binary).unwrap();
+    let elf = addr2line::object::File::parse(&*data).unwrap();
+    let foo = elf.symbols().find(|x| x.name() == Ok("foo")).unwrap();
+
+    let config = load("text_of(\"target\"), symbol_range(\"fo?\"), range(0x1001, 0x1fff)").unwrap();
+    let filters = config.ipt_filters();
+    assert_eq!(filters.len(), 3);
+    /* plain ranges come first */
+    assert_eq!(filters[0], (0x1000, 0x2000));
+    assert!(filters[1].0 <= foo.address() && filters[1].1 > foo.address());
+    assert_eq!(filters[2], (foo.address() & !0xfff, (foo.address() + foo.size() + 0xfff) & !0xfff));
+    assert!(filters.iter().all(|(a, b)| a & 0xfff == 0 && b & 0xfff == 0));
+
+    let err = load("symbol_range(\"parse_*\")").unwrap_err().to_string();
+    assert!(err.contains("symbol_range(\"parse_*\"): no matching function symbols found"), "{}", err);
+    assert!(load("section(\"other\", \".text\")").unwrap_err().to_string().contains("no (non-PIE) ELF executable named other"));
+    assert!(load("range(0x2000, 0x1000)").unwrap_err().to_string().contains("empty range"));
+    assert!(load("range(1, 2), range(3, 4), range(5, 6), text_of(\"target\"), range(7, 8)").is_err());
+
+    fs::remove_dir_all(&sharedir).unwrap();
+}
+
+#[test]
+fn coverage_map() {
+    use coverage_map::{CoverageMap, NyxNewBits};
+
+    let mut trace = vec![0_u8; 21];
+    trace[3] = 3;
+    trace[9] = 200;
+    trace[20] = 5;
+    coverage_map::classify_counts(&mut trace);
+    assert_eq!((trace[3], trace[9], trace[20]), (4, 128, 8));
+    assert_eq!(coverage_map::iter_set(&trace).collect::<Vec<_>>(), vec![(3, 4), (9, 128), (20, 8)]);
+    assert_eq!(coverage_map::count_set(&trace), 3);
+
+    let mut map = CoverageMap::new(trace.len());
+    assert_eq!(map.would_have_new_bits(&trace), NyxNewBits::NewEdge);
+    assert_eq!(map.has_new_bits(&trace), NyxNewBits::NewEdge);
+    assert_eq!(map.has_new_bits(&trace), NyxNewBits::None);
+    assert_eq!(map.count_covered(), 3);
+
+    /* same edge, different hit count class */
+    trace[9] = 1;
+    assert_eq!(map.has_new_bits(&trace), NyxNewBits::NewCount);
+    trace[0] = 1;
+    assert_eq!(map.would_have_new_bits(&trace), NyxNewBits::NewEdge);
+
+    let hash = coverage_map::hash(&trace);
+    assert_eq!(hash, coverage_map::hash(&trace.clone()));
+    assert_ne!(hash, coverage_map::hash(&trace[1..]));
+    assert_eq!(coverage_map::hash(b""), 0xcbf29ce484222325);
+}
+
+#[test]
+fn global_coverage() {
+    use coverage_map::NyxNewBits;
+    use global_coverage::GlobalCoverageMap;
+
+    let workdir = test_workdir("global_coverage");
+    fs::create_dir_all(&workdir).unwrap();
+
+    /* two workers of the same campaign */
+    let worker_0 = GlobalCoverageMap::open(&workdir, 20).unwrap();
+    let worker_1 = GlobalCoverageMap::open(&workdir, 20).unwrap();
+    assert!(GlobalCoverageMap::open(&workdir, 0x10000).is_err());
+
+    let mut trace = vec![0_u8; 20];
+    trace[2] = 1;
+    trace[19] = 4;
+    assert_eq!(worker_0.merge(&trace), NyxNewBits::NewEdge);
+    assert_eq!(worker_1.merge(&trace), NyxNewBits::None);
+    trace[19] = 8;
+    assert_eq!(worker_1.merge(&trace), NyxNewBits::NewCount);
+    assert_eq!(worker_0.merge(&trace), NyxNewBits::None);
+    assert_eq!(worker_0.count_covered(), 2);
+
+    /* concurrent merges: exactly one worker observes a new edge */
+    trace[7] = 1;
+    let results: Vec<NyxNewBits> = std::thread::scope(|s| {
+        let handles: Vec<_> = [&worker_0, &worker_1].into_iter().map(|x| {
+            let trace = &trace;
+            s.spawn(move || x.merge(trace))
+        }).collect();
+        handles.into_iter().map(|x| x.join().unwrap()).collect()
+    });
+    assert_eq!(results.iter().filter(|x| **x == NyxNewBits::NewEdge).count(), 1);
+    assert_eq!(worker_1.count_covered(), 3);
+
+    drop(worker_0);
+    drop(worker_1);
+    fs::remove_dir_all(&workdir).unwrap();
+
+    /* local and global map for the same execution: the bitmap is classified only once */
+    let mut mock = MockQemuNyx::new(&workdir, 1);
+    mock.push_response(MockResponse::Coverage(vec![(5, 3)]));
+    let (mut process, handle) = spawn(mock);
+    let global = GlobalCoverageMap::open(&workdir, process.bitmap_buffer_size()).unwrap();
+    let mut local = coverage_map::CoverageMap::for_process(&process);
+
+    process.exec().unwrap();
+    assert_eq!(process.update_coverage(&mut local), NyxNewBits::NewEdge);
+    assert_eq!(process.update_global_coverage(&global), NyxNewBits::NewEdge);
+    assert_eq!(process.bitmap_buffer()[5], 4);
+    assert_eq!(global.merge(process.classify_bitmap()), NyxNewBits::None);
+
+    drop(global);
+    teardown(process, handle, &workdir);
+}
+
+#[test]
+fn ijon() {
+    let workdir = test_workdir("ijon");
+    let mut mock = MockQemuNyx::new(&workdir, 1);
+    mock.set_agent_ijon_trace_bitmap(true);
+    mock.push_response(MockResponse::Ijon { executed_opcode_num: 1337, max: vec![(0, 5), (3, 7)] });
+    mock.push_response(MockResponse::Ijon { executed_opcode_num: 42, max: vec![(0, 9), (255, 1)] });
+    mock.push_response(MockResponse::Success);
+    let (mut process, handle) = spawn(mock);
+
+    assert_eq!(process.ijon_max_slots(), 256);
+    let mut aggregator = ijon::IjonAggregator::for_process(&process);
+
+    assert_eq!(process.exec().unwrap(), NyxReturnValue::Normal);
+    assert_eq!(process.ijon_executed_opcode_num(), 1337);
+    assert_eq!((process.ijon_max(0), process.ijon_max(3), process.ijon_max(256)), (Some(5), Some(7), None));
+    assert_eq!(process.update_ijon(&mut aggregator), vec![0, 3]);
+
+    /* the buffer is cleared before every execution */
+    assert_eq!(process.exec().unwrap(), NyxReturnValue::Normal);
+    assert_eq!(process.ijon_executed_opcode_num(), 42);
+    assert_eq!(process.ijon_max(3), Some(0));
+    assert_eq!(process.update_ijon(&mut aggregator), vec![0, 255]);
+    assert_eq!((aggregator.max[0], aggregator.max[3], aggregator.max[255]), (9, 7, 1));
+
+    process.set_ijon_reset(false);
+    assert_eq!(process.exec().unwrap(), NyxReturnValue::Normal);
+    assert_eq!(process.ijon_max(0), Some(9));
+    assert!(process.update_ijon(&mut aggregator).is_empty());
+
+    teardown(process, handle, &workdir);
+}
+
+#[test]
+fn corpus() {
+    use corpus::{Corpus, NyxCorpusKind};
+
+    let workdir = test_workdir("corpus");
+    let mut mock = MockQemuNyx::new(&workdir, 1);
+    mock.push_response(MockResponse::Crash("segfault\nat 0x4011a2".to_string()));
+    let (mut process, handle) = spawn(mock);
+    let corpus = Corpus::open(&workdir, 3).unwrap();
+
+    process.set_input(b"AAAA", 4);
+    assert_eq!(process.current_input(), b"AAAA");
+    let result = process.exec_result().unwrap();
+    let hash = process.store_result(&corpus, &result).unwrap().unwrap();
+    assert_eq!(hash, "e2512172abf8cc9f67fdd49eb6cacf2df71bbad3");
+    assert!(process.store_result(&corpus, &result).unwrap().is_none());
+    assert!(corpus.contains(NyxCorpusKind::Crash, &hash));
+    assert!(!corpus.contains(NyxCorpusKind::Normal, &hash));
+    assert_eq!(corpus.load(NyxCorpusKind::Crash, &hash).unwrap(), b"AAAA");
+
+    let metadata = corpus.metadata(NyxCorpusKind::Crash, &hash).unwrap();
+    assert_eq!((metadata.worker_id, metadata.value), (3, NyxReturnValue::Crash));
+    assert_eq!(metadata.message.as_deref(), Some("segfault\nat 0x4011a2"));
+    assert_eq!(corpus::CorpusMetadata::parse(&metadata.serialize()), Some(metadata.clone()));
+
+    /* concurrent workers: every input is reported as new exactly once */
+    let new: Vec<usize> = std::thread::scope(|s| {
+        let handles: Vec<_> = (0..4).map(|i| {
+            let corpus = Corpus::open(&workdir, i).unwrap();
+            let metadata = &metadata;
+            s.spawn(move || (0..16_u8).filter(|x| corpus.store(NyxCorpusKind::Normal, &[*x], metadata).unwrap().is_some()).count())
+        }).collect();
+        handles.into_iter().map(|x| x.join().unwrap()).collect()
+    });
+    assert_eq!(new.iter().sum::<usize>(), 16);
+    assert_eq!(corpus.entries(NyxCorpusKind::Normal).unwrap().len(), 16);
+    assert_eq!(fs::read_dir(corpus.path(NyxCorpusKind::Normal)).unwrap().count(), 32);
+
+    /* a sidecar left behind without input (e.g. by a killed worker) is reused */
+    let hash = corpus::Corpus::hash(b"BBBB");
+    fs::write(corpus.metadata_path(NyxCorpusKind::Timeout, &hash), metadata.serialize()).unwrap();
+    assert_eq!(corpus.store(NyxCorpusKind::Timeout, b"BBBB", &metadata).unwrap(), Some(hash.clone()));
+    assert_eq!(corpus.metadata(NyxCorpusKind::Timeout, &hash).unwrap(), metadata);
+
+    teardown(process, handle, &workdir);
+}
+
+#[test]
+fn crash_classification() {
+    use crash::{CrashInfo, NyxCrashClass};
+
+    let kasan = concat!(
+        "==================================================================\n",
+        "BUG: KASAN: slab-use-after-free in vuln_ioctl+0x1a2/0x2f0 [vuln]\n",
+        "Read of size 8 at addr ffff888012345678 by task poc/123\n",
+        "\n",
+        "CPU: 0 PID: 123 Comm: poc Not tainted 6.1.0 #1\n",
+        "Call Trace:\n",
+        " <TASK>\n",
+        " dump_stack_lvl+0x48/0x5f\n",
+        " print_report+0x184/0x4b1\n",
+        " ? vuln_ioctl+0x1a2/0x2f0 [vuln]\n",
+        " kasan_report+0xc9/0x100\n",
+        " vuln_ioctl+0x1a2/0x2f0 [vuln]\n",
+        " __x64_sys_ioctl.cold+0x90/0xd0\n",
+        " do_syscall_64+0x3b/0x90\n",
+        " </TASK>\n",
+        "\n",
+        "Allocated by task 123:\n",
+        " kmalloc_trace+0x25/0x90\n",
+    );
+    let info = CrashInfo::parse(kasan);
+    assert_eq!(info.class, NyxCrashClass::KasanUseAfterFree);
+    assert_eq!(info.fault_address, Some(0xffff888012345678));
+    assert_eq!(info.frames, vec!["vuln_ioctl", "__x64_sys_ioctl", "do_syscall_64"]);
+    assert_eq!(info.class.return_value(), NyxReturnValue::Asan);
+    assert_eq!(info.class.return_value().to_string(), "Asan");
+
+    let asan = concat!(
+        "==4242==ERROR: AddressSanitizer: heap-buffer-overflow on address 0x602000000011 at pc 0x4f5e3 bp 0x7ffd sp 0x7ffc\n",
+        "WRITE of size 1 at 0x602000000011 thread T0\n",
+        "    #0 0x4f5e3 in parse_header /src/parser.c:12:3\n",
+        "    #1 0x4f7a0 in main /src/main.c:30:5\n",
+        "\n",
+        "0x602000000011 is located 0 bytes to the right of 1-byte region\n",
+        "allocated by thread T0 here:\n",
+        "    #0 0x49c1d in __interceptor_malloc\n",
+    );
+    let info = CrashInfo::parse(asan);
+    assert_eq!(info.class, NyxCrashClass::AsanHeapOverflow);
+    assert_eq!(info.fault_address, Some(0x602000000011));
+    assert_eq!(info.frames, vec!["parse_header", "main"]);
+
+    let oops = concat!(
+        "BUG: kernel NULL pointer dereference, address: 0000000000000008\n",
+        "RIP: 0010:vuln_release+0x12/0x40 [vuln]\n",
+        "RSP: 0018:ffffc90000157e58 EFLAGS: 00010246\n",
+        "Call Trace:\n",
+        " __fput+0x93/0x250\n",
+        "Kernel panic - not syncing: Fatal exception\n",
+    );
+    let info = CrashInfo::parse(oops);
+    assert_eq!(info.class, NyxCrashClass::KernelOops);
+    assert_eq!(info.fault_address, Some(8));
+    assert_eq!(info.frames, vec!["vuln_release", "__fput"]);
+    assert_eq!(info.class.corpus_kind(), corpus::NyxCorpusKind::Crash);
+
+    assert_eq!(CrashInfo::parse("kernel BUG at mm/slub.c:42!").class, NyxCrashClass::KernelBug);
+    assert_eq!(CrashInfo::parse("target terminated by SIGSEGV").class, NyxCrashClass::Segv);
+    assert_eq!(CrashInfo::parse("").class, NyxCrashClass::Unknown);
+
+    /* the signature only depends on the class and the top frames */
+    assert_eq!(CrashInfo::parse(kasan).stack_signature(), CrashInfo::parse(&kasan.replace("ffff888012345678", "ffff888087654321")).stack_signature());
+    assert_ne!(CrashInfo::parse(kasan).stack_signature(), CrashInfo::parse(asan).stack_signature());
+
+    let workdir = test_workdir("crash_classification");
+    let mut mock = MockQemuNyx::new(&workdir, 1);
+    mock.push_response(MockResponse::Crash(kasan.to_string()));
+    mock.push_response(MockResponse::Crash("target terminated by SIGSEGV".to_string()));
+    let (mut process, handle) = spawn(mock);
+    let corpus = corpus::Corpus::open(&workdir, 0).unwrap();
+
+    process.set_input(b"kasan", 5);
+    let result = process.exec_result().unwrap();
+    assert_eq!(result.value, NyxReturnValue::Asan);
+    assert_eq!(process.crash_info().class, NyxCrashClass::KasanUseAfterFree);
+    let hash = process.store_result(&corpus, &result).unwrap().unwrap();
+    assert!(corpus.contains(corpus::NyxCorpusKind::Kasan, &hash));
+
+    assert_eq!(process.exec().unwrap(), NyxReturnValue::Crash);
+
+    teardown(process, handle, &workdir);
+}
+
+#[test]
+fn crash_dedup() {
+    use crash_dedup::{CrashBucket, CrashDedup};
+
+    let report = |addr: &str| format!(concat!(
+        "==4242==ERROR: AddressSanitizer: heap-use-after-free on address {} at pc 0x4f5e3 bp 0x7ffd sp 0x7ffc\n",
+        "READ of size 4 at {} thread T0\n",
+        "    #0 0x4f5e3 in parse_header /src/parser.c:12:3\n",
+        "    #1 0x4f7a0 in main /src/main.c:30:5\n",
+    ), addr, addr);
+
+    assert_eq!(crash_dedup::normalize_message(&report("0x602000000011")), "ERROR: AddressSanitizer: heap-use-after-free on address 0x? at pc 0x? bp 0x? sp 0x?");
+    assert_eq!(crash_dedup::mask_addresses("Read of size 8 at addr ffff888012345678 by task poc/123"), "Read of size 8 at addr ? by task poc/123");
+
+    let bucket = CrashBucket::new(&report("0x602000000011"), 0);
+    assert_eq!(bucket.frames, vec!["parse_header", "main"]);
+    assert_eq!(bucket.key(), CrashBucket::new(&report("0x6020000000f0"), 0).key());
+    assert_ne!(bucket.key(), CrashBucket::new(&report("0x602000000011"), 1).key());
+    assert_ne!(bucket.key(), CrashBucket::new(&report("0x602000000011").replace("parse_header", "parse_body"), 0).key());
+
+    let workdir = test_workdir("crash_dedup");
+    let mut mock = MockQemuNyx::new(&workdir, 1);
+    mock.push_response(MockResponse::Crash(report("0x602000000011")));
+    mock.push_response(MockResponse::Crash(report("0x602000000042")));
+    mock.push_response(MockResponse::Crash(report("0x602000000011")));
+    mock.push_response(MockResponse::Crash(report("0x602000000011")));
+    let (mut process, handle) = spawn(mock);
+
+    /* two workers of the same campaign */
+    let mut worker_0 = CrashDedup::open(&workdir).unwrap();
+    let mut worker_1 = CrashDedup::open(&workdir).unwrap();
+
+    assert_eq!(process.exec().unwrap(), NyxReturnValue::Asan);
+    assert!(process.is_new_unique_crash(&mut worker_0).unwrap());
+    assert!(!process.is_new_unique_crash(&mut worker_0).unwrap());
+    assert!(!process.is_new_unique_crash(&mut worker_1).unwrap());
+
+    /* same crash via a different coverage path */
+    assert_eq!(process.exec().unwrap(), NyxReturnValue::Asan);
+    assert!(!process.is_new_unique_crash(&mut worker_1).unwrap());
+    process.bitmap_buffer_mut()[7] = 1;
+    assert!(process.is_new_unique_crash(&mut worker_1).unwrap());
+    assert!(worker_0.contains(process.crash_bucket().key()));
+
+    /* the bucket does not depend on whether the bitmap has already been classified by update_coverage() */
+    let mut map = coverage_map::CoverageMap::for_process(&process);
+    process.exec().unwrap();
+    process.bitmap_buffer_mut()[7] = 3;
+    process.update_coverage(&mut map);
+    assert!(process.is_new_unique_crash(&mut worker_0).unwrap());
+    process.exec().unwrap();
+    process.bitmap_buffer_mut()[7] = 3;
+    assert!(!process.is_new_unique_crash(&mut worker_1).unwrap());
+
+    /* buckets are persisted in the workdir */
+    assert_eq!(CrashDedup::open(&workdir).unwrap().buckets().unwrap().len(), 3);
+
+    teardown(process, handle, &workdir);
+}
+
+#[test]
+fn respawn_policy() {
+    let workdir = test_workdir("respawn_policy");
+    let mut mock = MockQemuNyx::new(&workdir, 1);
+    mock.set_restarts(1);
+    mock.push_response(MockResponse::Disconnect);
+    let (mut process, handle) = spawn(mock);
+    process.set_respawn_policy(Some(RespawnPolicy { max_retries: 2, backoff: std::time::Duration::from_millis(10) }));
+    let aux_ptr = process.aux_buffer_as_mut_ptr();
+
+    /* the failed execution is reported, but the runner is usable again */
+    assert!(process.exec().is_err());
+    assert_eq!(process.aux_buffer_as_mut_ptr(), aux_ptr);
+    assert_eq!(process.exec().unwrap(), NyxReturnValue::Normal);
+    assert_eq!(handle.log().boots, 2);
+
+    teardown(process, handle, &workdir);
+}
+
+#[test]
+fn respawn_policy_gives_up() {
+    let workdir = test_workdir("respawn_policy_gives_up");
+    let mut mock = MockQemuNyx::new(&workdir, 1);
+    mock.push_response(MockResponse::Disconnect);
+    let mut params = mock.qemu_params();
+    params.startup_timeout = std::time::Duration::from_millis(50);
+    let handle = mock.spawn().unwrap();
+    let mut process = NyxProcess::from_runner(Box::new(QemuProcess::new(params).unwrap()));
+    process.set_respawn_policy(Some(RespawnPolicy { max_retries: 2, backoff: std::time::Duration::from_millis(100) }));
+
+    /* no further QEMU-Nyx instance comes up: the backoff doubles and the last error is returned */
+    let start = std::time::Instant::now();
+    assert!(matches!(process.exec(), Err(NyxError::ControlSocketTimeout)));
+    assert!(start.elapsed() >= std::time::Duration::from_millis(300));
+    assert_eq!(handle.log().boots, 1);
+
+    teardown(process, handle, &workdir);
+}
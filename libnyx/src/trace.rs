@@ -0,0 +1,91 @@
+/*
+    libnyx trace mode support
+
+    Copyright (C) 2021 Sergej Schumilo
+    This file is part of libnyx.
+
+    libnyx is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 2 of the License, or
+    (at your option) any later version.
+    libnyx is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+    You should have received a copy of the GNU General Public License
+    along with libnyx.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::collections::HashSet;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
+
+use super::*;
+
+/* In trace mode QEMU-Nyx appends all decoded transitions ("<from>,<to>,<count>") to this file (in redqueen_workdir_<id>). */
+pub const TRACE_RESULTS_FILE: &str = "pt_trace_results.txt";
+
+/* libxdc reports the start / end of a trace as transitions from / to this address */
+const TRACE_INIT_IP: u64 = 0xffffffffffffffff;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NyxEdge {
+    pub from: u64,
+    pub to: u64,
+}
+
+fn parse_trace_line(line: &str) -> Option<(u64, u64)> {
+    let mut fields = line.trim_matches(|c: char| c == '\0' || c.is_whitespace()).split(',');
+    let from = u64::from_str_radix(fields.next()?, 16).ok()?;
+    let to = u64::from_str_radix(fields.next()?, 16).ok()?;
+
+    if from == TRACE_INIT_IP || to == TRACE_INIT_IP {
+        return None;
+    }
+    Some((from, to))
+}
+
+/* Parses the content of a trace results file into a list of unique edges (in order of appearance). */
+pub fn parse_trace_results(data: &str) -> Vec<(u64, u64)> {
+    let mut known = HashSet::new();
+    data.lines()
+        .filter_map(parse_trace_line)
+        .filter(|x| known.insert(*x))
+        .collect()
+}
+
+impl NyxProcess {
+
+    /* Runs the given input in trace mode and returns the executed edges (deduplicated).
+     * The previous trace mode setting is restored afterwards.
+     */
+    pub fn trace(&mut self, input: &[u8]) -> Result<Vec<(u64, u64)>, NyxError> {
+        let results_path = match self.process.redqueen_workdir() {
+            Some(x) => format!("{}/{}", x, TRACE_RESULTS_FILE),
+            None => return Err(NyxError::InvalidConfig("trace mode is not supported by this runner".to_string())),
+        };
+
+        /* QEMU-Nyx might still hold the file open (O_APPEND) -> truncate instead of removing it */
+        match OpenOptions::new().write(true).open(&results_path) {
+            Ok(x) => x.set_len(0)?,
+            Err(x) if x.kind() == io::ErrorKind::NotFound => {},
+            Err(x) => return Err(NyxError::Io(x)),
+        }
+
+        let trace_mode = self.process.aux_buffer().config.trace_mode != 0;
+        self.set_input(input, input.len() as u32);
+        self.option_set_trace_mode(true);
+        self.option_apply();
+        let result = self.exec();
+        self.option_set_trace_mode(trace_mode);
+        self.option_apply();
+        result?;
+
+        match fs::read(&results_path) {
+            Ok(x) => Ok(parse_trace_results(&String::from_utf8_lossy(&x))),
+            Err(x) if x.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+            Err(x) => Err(NyxError::Io(x)),
+        }
+    }
+}